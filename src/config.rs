@@ -0,0 +1,85 @@
+use crate::slack::workspaces_dir;
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::io;
+use std::path::{self, Path};
+
+/// Defaults for global options, used when they aren't passed on the command line.
+/// Read from `config.json` in the config directory, e.g.
+/// `{"workspace": "work", "max_retries": 5, "timeout": 60, "concurrency": 8}`.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub workspace: Option<String>,
+    pub max_retries: Option<u32>,
+    pub timeout: Option<u64>,
+    pub concurrency: Option<usize>,
+}
+
+/// Reads the config at `path`, or else `<config dir>/tidy_slack/config.json` if it exists.
+pub fn load(path: Option<&Path>) -> Result<Config, Box<dyn Error>> {
+    let (path, required) = match path {
+        Some(path) => (path.to_path_buf(), true),
+        None => match workspaces_dir() {
+            Some(dir) => (dir.join("config.json"), false),
+            None => return Ok(Config::default()),
+        },
+    };
+    let json = match fs::read_to_string(&path) {
+        Ok(json) => json,
+        Err(error) if !required && error.kind() == io::ErrorKind::NotFound => {
+            return Ok(Config::default())
+        }
+        Err(error) => Err(format!(
+            "Couldn't read config from {}: {}",
+            path.display(),
+            error
+        ))?,
+    };
+    parse(&json)
+        .map_err(|error| format!("Couldn't parse config {}: {}", path.display(), error).into())
+}
+
+fn parse(json: &str) -> Result<Config, Box<dyn Error>> {
+    let config = serde_json::from_str::<Config>(json)?;
+    if let Some(workspace) = &config.workspace {
+        if workspace.is_empty() || workspace.contains(path::is_separator) {
+            Err(format!("`{}` isn't a valid workspace name", workspace))?;
+        }
+    }
+    if config.concurrency == Some(0) {
+        Err("concurrency must be at least 1")?;
+    }
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn parses_defaults_for_global_options() {
+        let config = parse(r#"{"workspace": "work", "max_retries": 5}"#).unwrap();
+        assert_eq!(config.workspace.as_deref(), Some("work"));
+        assert_eq!(config.max_retries, Some(5));
+        assert_eq!(config.timeout, None);
+
+        assert!(parse(r#"{"max_retry": 5}"#).is_err());
+        assert!(parse(r#"{"workspace": "../work"}"#).is_err());
+        assert!(parse(r#"{"concurrency": 0}"#).is_err());
+    }
+
+    #[test]
+    fn explicit_config_must_exist() {
+        let path = env::temp_dir().join(format!("tidy_slack-config-{}.json", std::process::id()));
+        let error = load(Some(&path)).unwrap_err().to_string();
+        assert!(error.starts_with("Couldn't read config from"));
+
+        fs::write(&path, "{").unwrap();
+        let error = load(Some(&path)).unwrap_err().to_string();
+        fs::remove_file(&path).unwrap();
+        assert!(error.starts_with("Couldn't parse config"));
+    }
+}
@@ -0,0 +1,417 @@
+//! Boolean expressions for `ls --filter-expr`.
+//!
+//! ```text
+//! expression := and ("OR" and)*
+//! and        := unary ("AND" unary)*
+//! unary      := "NOT" unary | "(" expression ")" | comparison | flag
+//! comparison := field operator value
+//! field      := "type" | "archived" | "members" | "name"
+//! operator   := "=" | "!=" | "<" | "<=" | ">" | ">=" | "~"
+//! flag       := "archived" | type
+//! type       := "public_channel" | "private_channel" | "mpim" | "im" | "public" | "private"
+//! ```
+//!
+//! Keywords are case-insensitive, and values containing spaces or operators can be quoted,
+//! e.g. `private AND (archived OR members<3) AND NOT name~"test"`.
+//! `~` matches names containing the value,
+//! and DMs never match comparisons on `members` since Slack doesn't count them.
+
+use crate::TYPES;
+
+/// What an expression is evaluated against.
+pub trait Subject {
+    fn type_name(&self) -> &str;
+    fn is_archived(&self) -> bool;
+    fn members(&self) -> Option<u64>;
+    fn names(&self) -> &[String];
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Type(String),
+    Archived(bool),
+    Members(Operator, u64),
+    Name(Operator, String),
+}
+impl Expr {
+    pub fn parse(input: &str) -> Result<Expr, String> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let expr = parser.or()?;
+        match parser.next() {
+            None => Ok(expr),
+            Some(token) => Err(format!(
+                "Unexpected {} after the end of the expression",
+                token
+            )),
+        }
+    }
+
+    pub fn matches(&self, subject: &dyn Subject) -> bool {
+        match self {
+            Expr::And(left, right) => left.matches(subject) && right.matches(subject),
+            Expr::Or(left, right) => left.matches(subject) || right.matches(subject),
+            Expr::Not(expr) => !expr.matches(subject),
+            Expr::Type(kind) => subject.type_name() == kind,
+            Expr::Archived(archived) => subject.is_archived() == *archived,
+            Expr::Members(operator, value) => subject
+                .members()
+                .is_some_and(|members| operator.compare(&members, value)),
+            Expr::Name(Operator::Contains, value) => subject
+                .names()
+                .iter()
+                .any(|name| name.contains(value.as_str())),
+            Expr::Name(Operator::Ne, value) => !subject.names().iter().any(|name| name == value),
+            Expr::Name(_, value) => subject.names().iter().any(|name| name == value),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+impl Operator {
+    fn compare<T: PartialOrd>(self, left: &T, right: &T) -> bool {
+        match self {
+            Operator::Eq => left == right,
+            Operator::Ne => left != right,
+            Operator::Lt => left < right,
+            Operator::Le => left <= right,
+            Operator::Gt => left > right,
+            Operator::Ge => left >= right,
+            Operator::Contains => false,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Operator(Operator),
+    Open,
+    Close,
+}
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "`{}`", word),
+            Token::Quoted(text) => write!(f, "\"{}\"", text),
+            Token::Operator(operator) => write!(
+                f,
+                "`{}`",
+                match operator {
+                    Operator::Eq => "=",
+                    Operator::Ne => "!=",
+                    Operator::Lt => "<",
+                    Operator::Le => "<=",
+                    Operator::Gt => ">",
+                    Operator::Ge => ">=",
+                    Operator::Contains => "~",
+                }
+            ),
+            Token::Open => write!(f, "`(`"),
+            Token::Close => write!(f, "`)`"),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => text.push(c),
+                        None => return Err("Missing closing `\"`".to_string()),
+                    }
+                }
+                tokens.push(Token::Quoted(text));
+            }
+            '=' | '!' | '<' | '>' | '~' => {
+                chars.next();
+                let equals = chars.peek() == Some(&'=');
+                if equals {
+                    chars.next();
+                }
+                tokens.push(Token::Operator(match (c, equals) {
+                    ('=', _) => Operator::Eq,
+                    ('!', true) => Operator::Ne,
+                    ('<', false) => Operator::Lt,
+                    ('<', true) => Operator::Le,
+                    ('>', false) => Operator::Gt,
+                    ('>', true) => Operator::Ge,
+                    ('~', false) => Operator::Contains,
+                    _ => {
+                        return Err(format!(
+                            "Unknown operator `{}{}`",
+                            c,
+                            if equals { "=" } else { "" }
+                        ))
+                    }
+                }));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "()\"=!<>~".contains(c) {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<&Token> {
+        self.position += 1;
+        self.tokens.get(self.position - 1)
+    }
+
+    /// Consumes the next token if it's the keyword `keyword`.
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.unary()?;
+        while self.keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        match self.next() {
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(expr),
+                    _ => Err("Missing closing `)`".to_string()),
+                }
+            }
+            Some(Token::Word(word)) => {
+                let field = word.to_lowercase();
+                match self.peek() {
+                    Some(Token::Operator(operator)) => {
+                        let operator = *operator;
+                        self.position += 1;
+                        let value = match self.next() {
+                            Some(Token::Word(value)) | Some(Token::Quoted(value)) => value.clone(),
+                            _ => return Err(format!("Missing value after `{}`", field)),
+                        };
+                        comparison(&field, operator, value)
+                    }
+                    _ => flag(&field),
+                }
+            }
+            Some(token) => Err(format!("Expected a condition, found {}", token)),
+            None => Err("Expected a condition, found the end of the expression".to_string()),
+        }
+    }
+}
+
+fn comparison(field: &str, operator: Operator, value: String) -> Result<Expr, String> {
+    let expr = match (field, operator) {
+        ("type", Operator::Eq) => Expr::Type(type_name(&value)?),
+        ("type", Operator::Ne) => Expr::Not(Box::new(Expr::Type(type_name(&value)?))),
+        ("archived", Operator::Eq) | ("archived", Operator::Ne) => {
+            let archived = match value.to_lowercase().as_str() {
+                "true" | "yes" => true,
+                "false" | "no" => false,
+                _ => return Err(format!("`archived` must be true or false, not `{}`", value)),
+            };
+            Expr::Archived(archived == (operator == Operator::Eq))
+        }
+        ("members", Operator::Contains) => {
+            return Err("`members` can't be compared with `~`".to_string())
+        }
+        ("members", _) => Expr::Members(
+            operator,
+            value
+                .parse()
+                .map_err(|_| format!("`members` must be compared to a number, not `{}`", value))?,
+        ),
+        ("name", Operator::Eq) | ("name", Operator::Ne) | ("name", Operator::Contains) => {
+            Expr::Name(operator, value)
+        }
+        ("type", _) | ("archived", _) | ("name", _) => {
+            return Err(format!("`{}` can only be compared with =, !=, or ~", field))
+        }
+        _ => {
+            return Err(format!(
+                "Unknown field `{}`. Use type, archived, members, or name.",
+                field
+            ))
+        }
+    };
+    Ok(expr)
+}
+
+fn flag(word: &str) -> Result<Expr, String> {
+    match word {
+        "archived" => Ok(Expr::Archived(true)),
+        _ => type_name(word).map(Expr::Type).map_err(|_| {
+            format!(
+                "Unknown condition `{}`. Use archived, a type, or a comparison like members<3.",
+                word
+            )
+        }),
+    }
+}
+
+/// Full name of a conversation type, accepting `public` and `private` as shorthands.
+fn type_name(value: &str) -> Result<String, String> {
+    let value = value.to_lowercase();
+    let kind = match value.as_str() {
+        "public" => "public_channel",
+        "private" => "private_channel",
+        kind => kind,
+    };
+    if TYPES.contains(&kind) {
+        Ok(kind.to_string())
+    } else {
+        Err(format!(
+            "Unknown type `{}`. Valid types are {}.",
+            value,
+            TYPES.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Channel {
+        kind: &'static str,
+        archived: bool,
+        members: Option<u64>,
+        names: Vec<String>,
+    }
+    impl Subject for Channel {
+        fn type_name(&self) -> &str {
+            self.kind
+        }
+
+        fn is_archived(&self) -> bool {
+            self.archived
+        }
+
+        fn members(&self) -> Option<u64> {
+            self.members
+        }
+
+        fn names(&self) -> &[String] {
+            &self.names
+        }
+    }
+
+    fn channel(kind: &'static str, archived: bool, members: Option<u64>, name: &str) -> Channel {
+        Channel {
+            kind,
+            archived,
+            members,
+            names: vec![name.to_string()],
+        }
+    }
+
+    #[test]
+    fn combines_conditions() {
+        let expr = Expr::parse("private AND archived AND members<3").unwrap();
+        assert!(expr.matches(&channel("private_channel", true, Some(2), "old")));
+        assert!(!expr.matches(&channel("private_channel", true, Some(3), "old")));
+        assert!(!expr.matches(&channel("private_channel", false, Some(2), "old")));
+        assert!(!expr.matches(&channel("public_channel", true, Some(2), "old")));
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let expr = Expr::parse("im or type=public_channel and not name~\"test team\"").unwrap();
+        assert!(expr.matches(&channel("im", false, None, "alice")));
+        assert!(expr.matches(&channel("public_channel", false, Some(9), "general")));
+        assert!(!expr.matches(&channel("public_channel", false, Some(9), "test team")));
+
+        let expr = Expr::parse("(im OR public) AND archived=false").unwrap();
+        assert!(!expr.matches(&channel("im", true, None, "alice")));
+    }
+
+    #[test]
+    fn dms_never_match_member_counts() {
+        let expr = Expr::parse("members>=0").unwrap();
+        assert!(!expr.matches(&channel("im", false, None, "alice")));
+        assert!(expr.matches(&channel("public_channel", false, Some(0), "empty")));
+    }
+
+    #[test]
+    fn rejects_unknown_identifiers_and_malformed_expressions() {
+        assert!(Expr::parse("owner=alice")
+            .unwrap_err()
+            .contains("Unknown field"));
+        assert!(Expr::parse("shared")
+            .unwrap_err()
+            .contains("Unknown condition"));
+        assert!(Expr::parse("type=channels").is_err());
+        assert!(Expr::parse("members~3").is_err());
+        assert!(Expr::parse("members<lots").is_err());
+        assert!(Expr::parse("(im").is_err());
+        assert!(Expr::parse("im mpim").is_err());
+        assert!(Expr::parse("name=\"general").is_err());
+        assert!(Expr::parse("").is_err());
+    }
+}
@@ -1,22 +1,237 @@
 extern crate reqwest;
 
-use clap::{crate_version, App, AppSettings, Arg, ArgMatches, SubCommand};
+mod config;
+mod filter;
+mod mrkdwn;
+mod pacer;
+mod pager;
+mod slack;
+mod watermark;
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use clap::{
+    crate_version, value_t_or_exit, App, AppSettings, Arg, ArgGroup, ArgMatches, Shell, SubCommand,
+};
 use colored::*;
-use indicatif::{ProgressBar, ProgressStyle};
-use log::{info, LevelFilter};
+use filter::{Expr, Subject};
+use futures::channel::mpsc;
+use futures::future;
+use futures::stream::{self, LocalBoxStream, StreamExt, TryStreamExt};
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use log::{debug, error, info, trace, warn, LevelFilter};
 use num_format::{Locale, ToFormattedString};
-use reqwest::Client;
-use serde::Deserialize;
+use pacer::Pacer;
+use pager::Pager;
+use rand::rngs::StdRng;
+use rand::seq::index;
+use rand::SeedableRng;
+use reqwest::{Client, Url};
+use rusqlite::{params, Connection};
+use serde::Serialize;
+use slack::{
+    list_workspaces, mask_token, missing_deletion_scopes, read_token, workspaces_dir, Archival,
+    Conversation, Deletion, Edit, Message, ProfileField, Slack, SlackApi, Unarchival, User,
+};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::env;
 use std::error::Error;
-use std::time::Instant;
-use std::{fs, u64};
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Read, Write};
+use std::path::Path;
+use std::process;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-fn main() {
+#[tokio::main]
+async fn main() {
     let now = Instant::now();
 
-    let types = ["public_channel", "private_channel", "mpim", "im"];
+    let options = build_cli().get_matches();
+    let config = config::load(options.value_of("config").map(Path::new)).unwrap();
+
+    let filter = log_level(&options);
+    let locale = Locale::from_name(options.value_of("locale").unwrap()).unwrap();
+    let concurrency = configured(&options, "concurrency", config.concurrency);
+
+    let mut logger = env_logger::Builder::from_default_env();
+    logger.filter(Some(module_path!()), filter);
+    if options.value_of("log_format") == Some("json") {
+        // One object per line so log aggregators can ingest them
+        logger.format(|buf, record| {
+            writeln!(
+                buf,
+                "{}",
+                serde_json::json!({
+                    "timestamp": Utc::now().to_rfc3339(),
+                    "level": record.level().to_string(),
+                    "target": record.target(),
+                    "message": record.args().to_string(),
+                })
+            )
+        });
+    }
+    logger.init();
+
+    // Icons still distinguish archived, deleted, and other conversations without colors
+    if options.is_present("no_color")
+        || env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+        || !io::stdout().is_terminal()
+    {
+        colored::control::set_override(false);
+    }
+
+    let slack = Slack::new(
+        Client::builder()
+            .timeout(Duration::from_secs(configured(
+                &options,
+                "timeout",
+                config.timeout,
+            )))
+            .build()
+            .unwrap(),
+        configured(&options, "max_retries", config.max_retries),
+        options
+            .values_of_lossy("retry_on_error")
+            .unwrap_or_default(),
+        options
+            .value_of("max_pages")
+            .map(|pages| pages.parse().unwrap()),
+        options.value_of("cookie").map(|cookie| cookie.to_string()),
+        options
+            .value_of("workspace")
+            .map(|workspace| workspace.to_string())
+            .or(config.workspace),
+        if options.is_present("token_stdin") {
+            Some(read_token(&mut io::stdin().lock()).unwrap())
+        } else {
+            None
+        },
+    )
+    .with_api_base(options.value_of("api_base").unwrap());
+
+    if let Ok(token) = slack.token() {
+        debug!("Authenticating with token {}", mask_token(&token));
+        if slack.cookie.is_none() && token.trim_start_matches("Bearer ").starts_with("xoxc-") {
+            warn!("Browser session tokens (xoxc-...) require --cookie or SLACK_COOKIE to be set");
+        }
+    }
+
+    if let Some(cmd) = options.subcommand_name() {
+        let sub_options = options.subcommand_matches(cmd);
+        match cmd {
+            "archive" => archive(&slack, sub_options.unwrap()).await,
+            "completions" => completions(sub_options.unwrap()),
+            "edit" => exit_on_error(edit(&slack, sub_options.unwrap()).await),
+            "export" => export(&slack, sub_options.unwrap(), locale).await,
+            "files" => exit_on_error(files(&slack, sub_options.unwrap(), filter, locale).await),
+            "info" => info(&slack, sub_options.unwrap(), locale).await,
+            "ls" => ls(&slack, TYPES, sub_options, filter, locale, concurrency).await,
+            "purge" => exit_on_error(
+                purge(&slack, sub_options.unwrap(), filter, locale, concurrency).await,
+            ),
+            "read" => read(&slack, sub_options.unwrap(), filter, locale, concurrency).await,
+            "rm" => exit_on_error(
+                rm(&slack, sub_options.unwrap(), filter, locale, concurrency)
+                    .await
+                    .map(|_| ()),
+            ),
+            "stats" => stats(&slack, filter, locale).await,
+            "unarchive" => unarchive(&slack, sub_options.unwrap()).await,
+            "users" => users(&slack, sub_options).await,
+            "workspaces" => workspaces(),
+            _ => panic!("Unsupported command: {}", cmd),
+        }
+    };
+
+    let format: fn(Duration, Locale) -> String = if options.is_present("pretty_duration") {
+        format_duration
+    } else {
+        format_seconds
+    };
+    let (retries, waited) = slack.retries();
+    if retries > 0 {
+        info!(
+            "Command completed in {} (waited {} across {} retries)",
+            format(now.elapsed(), locale),
+            format(waited, locale),
+            retries.to_formatted_string(&locale)
+        );
+    } else {
+        info!("Command completed in {}", format(now.elapsed(), locale));
+    }
+}
+
+/// Logs the error of a command that stopped partway and exits with a failure status.
+fn exit_on_error(result: Result<(), Box<dyn Error>>) {
+    if let Err(error) = result {
+        error!("{}", error);
+        process::exit(1);
+    }
+}
+
+/// Logs how long each phase of a command took, for profiling with -vv.
+fn trace_timings(timings: &[(&str, Duration)], locale: Locale) {
+    for (phase, duration) in timings {
+        trace!("{} took {}", phase, format_seconds(*duration, locale));
+    }
+}
+
+/// Value of the global option `name` when passed on the command line,
+/// otherwise the `configured` value, falling back to the option's default.
+fn configured<T>(options: &ArgMatches<'_>, name: &str, configured: Option<T>) -> T
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    match configured {
+        Some(value) if options.occurrences_of(name) == 0 => value,
+        _ => value_t_or_exit!(options, name, T),
+    }
+}
+
+/// Formats `duration` as seconds with millisecond precision, e.g. `1,234.050s`.
+fn format_seconds(duration: Duration, locale: Locale) -> String {
+    format!(
+        "{}.{:03}s",
+        duration.as_secs().to_formatted_string(&locale),
+        duration.subsec_millis()
+    )
+}
+
+/// Formats `duration` in hours, minutes, and seconds, leaving out larger units that are zero
+/// and trailing zeros in the milliseconds, e.g. `2m 0.5s` or `1h 0m 3s`.
+fn format_duration(duration: Duration, locale: Locale) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes) = (seconds / 3600, seconds / 60 % 60);
+    let mut formatted = String::new();
+    if hours > 0 {
+        formatted.push_str(&format!("{}h ", hours.to_formatted_string(&locale)));
+    }
+    if hours > 0 || minutes > 0 {
+        formatted.push_str(&format!("{}m ", minutes));
+    }
+    let millis = format!("{:03}", duration.subsec_millis());
+    let millis = millis.trim_end_matches('0');
+    if millis.is_empty() {
+        formatted.push_str(&format!("{}s", seconds % 60));
+    } else {
+        formatted.push_str(&format!("{}.{}s", seconds % 60, millis));
+    }
+    formatted
+}
+
+const TYPES: [&str; 4] = ["public_channel", "private_channel", "mpim", "im"];
+const FIELDS: [&str; 7] = [
+    "id", "type", "name", "created", "members", "archived", "url",
+];
+/// Fields in CSV listings unless `--fields` says otherwise.
+/// Links take an extra request to Slack, so they're only included when asked for.
+const CSV_FIELDS: [&str; 6] = ["id", "type", "name", "created", "members", "archived"];
 
-    let options = App::new("Tidy Slack")
+fn build_cli() -> App<'static, 'static> {
+    App::new("Tidy Slack")
         .version(crate_version!())
         .author("Brandon Frohs <brandon@19.codes>")
         .about("Deletes messages from slack.")
@@ -30,565 +245,5085 @@ fn main() {
                     .long("exclude_archived")
                     .help("If provided, archived channels will be excluded.")
             )
+            .arg(
+                Arg::with_name("exclude_bots")
+                    .long("exclude-bots")
+                    .help("Leaves out direct messages with bots and apps, including Slackbot.")
+            )
+            .arg(
+                Arg::with_name("exclude_deleted_users")
+                    .long("exclude-deleted-users")
+                    .help("Leaves out direct messages with deactivated users, otherwise marked with 🗑.")
+            )
+            .arg(
+                Arg::with_name("only_member")
+                    .long("only-member")
+                    .help("Only lists conversations you're a member of, which are the only ones you can delete messages from.")
+            )
             .arg(
                 Arg::with_name("types")
                     .long("types")
-                    .possible_values(&types)
+                    .validator(validate_type)
+                    .takes_value(true)
+                    .multiple(true)
+                    .help("Types of conversations to list, or all of them. Listed as #public_channel, !private_channel, &mpim, and @im.")
+            )
+            .arg(
+                Arg::with_name("flag_duplicates")
+                    .long("flag-duplicates")
+                    .conflicts_with_all(&["stream", "json_lines"])
+                    .help("Marks channels whose names match or differ by a single character, ignoring dashes, underscores, and periods, and lists them together.")
+            )
+            .arg(
+                Arg::with_name("type_order")
+                    .long("type-order")
+                    .possible_values(&TYPES)
+                    .takes_value(true)
+                    .multiple(true)
+                    .require_delimiter(true)
+                    .help("Comma-separated types to group conversations by, in order, e.g. im,mpim. Types left out follow in their usual order.")
+            )
+            .arg(
+                Arg::with_name("no_im")
+                    .long("no-im")
+                    .alias("exclude-im")
+                    .conflicts_with("types")
+                    .help("Leaves direct messages out of the listing.")
+            )
+            .arg(
+                Arg::with_name("no_mpim")
+                    .long("no-mpim")
+                    .alias("exclude-mpim")
+                    .conflicts_with("types")
+                    .help("Leaves multi-person direct messages out of the listing.")
+            )
+            .arg(
+                Arg::with_name("team_id")
+                    .long("team-id")
+                    .takes_value(true)
+                    .help("Workspace to list conversations from. Required by some Enterprise Grid orgs.")
+            )
+            .arg(
+                Arg::with_name("start_cursor")
+                    .long("start-cursor")
+                    .takes_value(true)
+                    .help("Resumes an interrupted listing from the cursor logged with -v when it failed. Conversations created, archived, or renamed since then may be missed or repeated.")
+            )
+            .arg(
+                Arg::with_name("limit_total")
+                    .long("limit-total")
+                    .takes_value(true)
+                    .value_name("N")
+                    .validator(|value| match value.parse::<usize>() {
+                        Ok(0) => Err("Must be at least 1".to_string()),
+                        Ok(_) => Ok(()),
+                        Err(error) => Err(error.to_string()),
+                    })
+                    .help("Stops retrieving conversations once N have been retrieved, before filtering, for a quick look at a large workspace.")
+            )
+            .arg(
+                Arg::with_name("stream")
+                    .long("stream")
+                    .conflicts_with("type_order")
+                    .help("Prints each conversation as soon as it's normalized, in the order they arrive rather than sorted by type and name.")
+            )
+            .arg(
+                Arg::with_name("format")
+                    .long("format")
+                    .possible_values(&["plain", "table", "csv"])
+                    .takes_value(true)
+                    .conflicts_with("stream")
+                    .help("How to print conversations. `table` aligns icons, IDs, types, and names into columns. `csv` prints a header row and one row per conversation for spreadsheets.")
+            )
+            .arg(
+                Arg::with_name("fields")
+                    .long("fields")
+                    .possible_values(&FIELDS)
+                    .takes_value(true)
+                    .multiple(true)
+                    .require_delimiter(true)
+                    .help("Comma-separated columns to print, in order, e.g. id,name,members.")
+            )
+            .arg(
+                Arg::with_name("json")
+                    .long("json")
+                    .conflicts_with_all(&["format", "stream", "fields"])
+                    .help("Prints conversations as JSON.")
+            )
+            .arg(
+                Arg::with_name("json_lines")
+                    .long("json-lines")
+                    .conflicts_with_all(&["format", "fields", "head", "tail", "sample", "type_order"])
+                    .help("Prints each conversation as a line of JSON as soon as it's normalized, for piping into stream processors. Conversations won't be sorted.")
+            )
+            .group(ArgGroup::with_name("json_output").args(&["json", "json_lines"]))
+            .arg(
+                Arg::with_name("print_ids")
+                    .long("print-ids")
+                    .conflicts_with_all(&["format", "fields", "json", "json_lines"])
+                    .help("Prints only conversation IDs, one per line, for piping into other commands.")
+            )
+            .arg(
+                Arg::with_name("null")
+                    .long("null")
+                    .requires("print_ids")
+                    .help("Ends each ID with a null character instead of a newline, for `xargs -0`.")
+            )
+            .arg(
+                Arg::with_name("pretty")
+                    .long("pretty")
+                    .requires("json")
+                    .help("Indents the JSON output. Defaults to compact, single-line output.")
+            )
+            .arg(
+                Arg::with_name("include_members")
+                    .long("include-members")
+                    .requires("json_output")
+                    .help("Includes the user IDs of members of multi-person DMs in the JSON output.")
+            )
+            .arg(
+                Arg::with_name("show_unread")
+                    .long("show-unread")
+                    .help("Marks channels with messages newer than the last one you've read. Requires an extra request per channel.")
+            )
+            .arg(
+                Arg::with_name("show_shared")
+                    .long("show-shared")
+                    .help("Marks channels shared externally with other organizations or across an Enterprise Grid organization.")
+            )
+            .arg(
+                Arg::with_name("show_parent")
+                    .long("show-parent")
+                    .help("Shows the conversation each channel belongs to, e.g. for channels connected to other organizations. Requires an extra request per such channel.")
+            )
+            .arg(
+                Arg::with_name("show_url")
+                    .long("show-url")
+                    .help("Shows a link to open each conversation in the browser.")
+            )
+            .arg(
+                Arg::with_name("show_creator")
+                    .long("show-creator")
+                    .help("Shows who created each channel.")
+            )
+            .arg(
+                Arg::with_name("profile_field")
+                    .long("profile-field")
+                    .possible_values(&["name", "real_name", "display_name", "email"])
+                    .takes_value(true)
+                    .help("Which profile value to show for people in DMs. Defaults to their name.")
+            )
+            .arg(
+                Arg::with_name("output_file")
+                    .long("output-file")
+                    .takes_value(true)
+                    .help("Writes conversations to the provided file, without colors, instead of stdout.")
+            )
+            .arg(
+                Arg::with_name("no_pager")
+                    .long("no-pager")
+                    .help("Prints conversations directly instead of through $PAGER when stdout is a terminal.")
+            )
+            .arg(
+                Arg::with_name("exclude")
+                    .long("exclude")
                     .takes_value(true)
                     .multiple(true)
-                    .help("Types of conversations to list.")
+                    .number_of_values(1)
+                    .help("Excludes conversations with names that contain provided substring. May be repeated.")
+            )
+            .arg(
+                Arg::with_name("search_topics")
+                    .long("search-topics")
+                    .requires("SUBSTRING")
+                    .help("Also matches SUBSTRING against channel topics and purposes.")
+            )
+            .arg(
+                Arg::with_name("normalized")
+                    .long("normalized")
+                    .help("Also matches SUBSTRING and --exclude against Slack's normalized names, so `jose` finds `josé`.")
+            )
+            .arg(
+                Arg::with_name("filter_expr")
+                    .long("filter-expr")
+                    .takes_value(true)
+                    .validator(|value| Expr::parse(&value).map(|_| ()))
+                    .help("Only lists conversations matching a boolean expression over type, archived, members, and name, e.g. \"private AND (archived OR members<3) AND NOT name~test\". Comparisons are =, !=, <, <=, >, >=, and ~ (contains).")
+            )
+            .arg(
+                Arg::with_name("head")
+                    .long("head")
+                    .takes_value(true)
+                    .conflicts_with_all(&["tail", "stream"])
+                    .validator(|value| value.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                    .help("Only prints the first N conversations after sorting.")
+            )
+            .arg(
+                Arg::with_name("tail")
+                    .long("tail")
+                    .takes_value(true)
+                    .conflicts_with("stream")
+                    .validator(|value| value.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                    .help("Only prints the last N conversations after sorting.")
+            )
+            .arg(
+                Arg::with_name("sample")
+                    .long("sample")
+                    .takes_value(true)
+                    .value_name("N")
+                    .conflicts_with_all(&["head", "tail", "stream"])
+                    .validator(|value| value.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                    .help("Only prints N conversations picked at random from those that match.")
+            )
+            .arg(
+                Arg::with_name("seed")
+                    .long("seed")
+                    .takes_value(true)
+                    .requires("sample")
+                    .validator(|value| value.parse::<u64>().map(|_| ()).map_err(|e| e.to_string()))
+                    .help("Picks the same --sample every time for the same number and conversations.")
+            )
+            .arg(
+                Arg::with_name("min_members")
+                    .long("min-members")
+                    .takes_value(true)
+                    .validator(|value| value.parse::<u64>().map(|_| ()).map_err(|e| e.to_string()))
+                    .help("Only lists channels with at least provided number of members. Excludes DMs.")
+            )
+            .arg(
+                Arg::with_name("max_members")
+                    .long("max-members")
+                    .takes_value(true)
+                    .validator(|value| value.parse::<u64>().map(|_| ()).map_err(|e| e.to_string()))
+                    .help("Only lists channels with at most provided number of members. Excludes DMs.")
+            )
+            .arg(
+                Arg::with_name("match")
+                    .long("match")
+                    .possible_values(&["any", "all"])
+                    .takes_value(true)
+                    .requires("SUBSTRING")
+                    .help("Whether conversations must contain any or all of the provided substrings. Defaults to any.")
             )
             .arg(
                 Arg::with_name("SUBSTRING")
-                    .help("Narrows results down to those that contain provided substring.")
+                    .help("Narrows results down to those that contain provided substrings.")
+                    .multiple(true)
                     .index(1)
             )
         )
-        // Verbosity level
-        .arg(
-            Arg::with_name("silent")
-                .short("s")
-                .long("silent")
-                .conflicts_with_all(&["quiet", "verbose"])
-                .help("Silences all output."),
+        .subcommand(SubCommand::with_name("info")
+            .about("Show details of a single conversation.")
+            .arg(
+                Arg::with_name("CHANNEL")
+                    .help("ID or name of the conversation to show, e.g. C0123456789, #general, or @alice.")
+                    .required(true)
+                    .index(1)
+            )
         )
-        .arg(
-            Arg::with_name("quiet")
-                .short("q")
-                .multiple(true)
-                .long("quiet")
-                .conflicts_with("verbose")
-                .help("Shows less detail. -q shows less detail, -qq shows least detail, -qqq is equivalent to --silent."),
+        .subcommand(SubCommand::with_name("read")
+            .about("Mark conversations as read.")
+            .arg(
+                Arg::with_name("all")
+                    .long("all")
+                    .conflicts_with("CHANNEL")
+                    .help("Marks every conversation you're a member of as read.")
+            )
+            .arg(
+                Arg::with_name("CHANNEL")
+                    .help("ID or name of the conversation to mark as read, e.g. C0123456789, #general, or @alice.")
+                    .required_unless("all")
+                    .index(1)
+            )
         )
-        .arg(
-            Arg::with_name("verbose")
-                .short("v")
-                .multiple(true)
-                .long("verbose")
-                .help("Shows more detail. -v shows more detail, -vv shows most detail."),
+        .subcommand(SubCommand::with_name("rm")
+            .about("Delete messages from a conversation.")
+            .arg(
+                Arg::with_name("newer_than")
+                    .long("newer-than")
+                    .takes_value(true)
+                    .validator(|value| parse_duration(&value).map(|_| ()))
+                    .help("Only deletes messages newer than provided duration, e.g. 12h, 30d, or 2w.")
+            )
+            .arg(
+                Arg::with_name("older_than")
+                    .long("older-than")
+                    .takes_value(true)
+                    .validator(|value| parse_duration(&value).map(|_| ()))
+                    .help("Only deletes messages older than provided duration, e.g. 12h, 30d, or 2w.")
+            )
+            .arg(
+                Arg::with_name("after")
+                    .long("after")
+                    .takes_value(true)
+                    .conflicts_with("newer_than")
+                    .validator(|value| parse_date(&value).map(|_| ()))
+                    .help("Only deletes messages sent after provided date, e.g. 2024-01-31, 2024-01-31T09:00:00Z, or 1706691600.")
+            )
+            .arg(
+                Arg::with_name("before")
+                    .long("before")
+                    .takes_value(true)
+                    .conflicts_with("older_than")
+                    .validator(|value| parse_date(&value).map(|_| ()))
+                    .help("Only deletes messages sent before provided date, e.g. 2024-01-31, 2024-01-31T09:00:00Z, or 1706691600.")
+            )
+            .arg(
+                Arg::with_name("no_threads")
+                    .long("no-threads")
+                    .help("Leaves replies in threads alone, only deleting top-level messages.")
+            )
+            .arg(
+                Arg::with_name("include_general")
+                    .long("include-general")
+                    .help("Allows deleting messages from the channel every member of the workspace is in.")
+            )
+            .arg(
+                Arg::with_name("from")
+                    .long("from")
+                    .takes_value(true)
+                    .help("Only deletes messages sent by provided user ID or name.")
+            )
+            .arg(
+                Arg::with_name("count_only")
+                    .long("count-only")
+                    .help("Only prints how many messages would be deleted.")
+            )
+            .arg(
+                Arg::with_name("dry_run")
+                    .long("dry-run")
+                    .conflicts_with("count_only")
+                    .help("Only reports how many messages would be deleted, without asking or deleting anything.")
+            )
+            .arg(
+                Arg::with_name("interactive")
+                    .short("i")
+                    .long("interactive")
+                    .conflicts_with("CHANNEL")
+                    .help("Lists conversations you're a member of and asks which ones to delete messages from.")
+            )
+            .arg(
+                Arg::with_name("fail_fast")
+                    .long("fail-fast")
+                    .conflicts_with("continue")
+                    .help("Stops at the first message that fails to delete.")
+            )
+            .arg(
+                Arg::with_name("continue")
+                    .long("continue")
+                    .help("Logs messages that fail to delete and moves on to the next one (default).")
+            )
+            .arg(
+                Arg::with_name("CHANNEL")
+                    .help("IDs or names of the conversations to delete messages from, one after another, e.g. C0123456789, #general, or @alice.")
+                    .required_unless("interactive")
+                    .multiple(true)
+                    .index(1)
+            )
+        )
+        .subcommand(SubCommand::with_name("purge")
+            .about("Delete old messages from every conversation you're a member of.")
+            .arg(
+                Arg::with_name("older_than")
+                    .long("older-than")
+                    .takes_value(true)
+                    .required(true)
+                    .validator(|value| parse_duration(&value).map(|_| ()))
+                    .help("Deletes messages older than provided duration, e.g. 12h, 30d, or 2w.")
+            )
+            .arg(
+                Arg::with_name("no_threads")
+                    .long("no-threads")
+                    .help("Leaves replies in threads alone, only deleting top-level messages.")
+            )
+            .arg(
+                Arg::with_name("dry_run")
+                    .long("dry-run")
+                    .help("Only reports how many messages would be deleted from each conversation.")
+            )
+            .arg(
+                Arg::with_name("count_only")
+                    .long("count-only")
+                    .conflicts_with("dry_run")
+                    .help("Only prints how many messages would be deleted in total.")
+            )
+            .arg(
+                Arg::with_name("include_general")
+                    .long("include-general")
+                    .help("Allows deleting messages from the channel every member of the workspace is in.")
+            )
+            .arg(
+                Arg::with_name("fail_fast")
+                    .long("fail-fast")
+                    .conflicts_with("continue")
+                    .help("Stops at the first message that fails to delete.")
+            )
+            .arg(
+                Arg::with_name("continue")
+                    .long("continue")
+                    .help("Logs messages that fail to delete and moves on to the next one (default).")
+            )
+        )
+        .subcommand(SubCommand::with_name("edit")
+            .about("Replace the text of a message, e.g. to redact it instead of deleting it.")
+            .alias("update")
+            .arg(
+                Arg::with_name("text")
+                    .long("text")
+                    .takes_value(true)
+                    .empty_values(false)
+                    .help("New text for the message. Read from stdin when not provided.")
+            )
+            .arg(
+                Arg::with_name("CHANNEL")
+                    .help("ID or name of the conversation the message is in, e.g. C0123456789, #general, or @alice.")
+                    .required(true)
+                    .index(1)
+            )
+            .arg(
+                Arg::with_name("TS")
+                    .help("Timestamp of the message to edit, e.g. 1575000000.000100.")
+                    .required(true)
+                    .index(2)
+            )
+        )
+        .subcommand(SubCommand::with_name("archive")
+            .about("Archive a conversation.")
+            .arg(
+                Arg::with_name("CHANNEL")
+                    .help("ID or name of the conversation to archive, e.g. C0123456789 or #general.")
+                    .required(true)
+                    .index(1)
+            )
         )
-        .get_matches();
+        .subcommand(SubCommand::with_name("unarchive")
+            .about("Unarchive a conversation.")
+            .alias("restore")
+            .arg(
+                Arg::with_name("CHANNEL")
+                    .help("ID or name of the conversation to unarchive, e.g. C0123456789 or #general.")
+                    .required(true)
+                    .index(1)
+            )
+        )
+        .subcommand(SubCommand::with_name("export")
+            .about("Export messages from a conversation as newline-delimited JSON.")
+            .arg(
+                Arg::with_name("newer_than")
+                    .long("newer-than")
+                    .takes_value(true)
+                    .validator(|value| parse_duration(&value).map(|_| ()))
+                    .help("Only exports messages newer than provided duration, e.g. 12h, 30d, or 2w.")
+            )
+            .arg(
+                Arg::with_name("older_than")
+                    .long("older-than")
+                    .takes_value(true)
+                    .validator(|value| parse_duration(&value).map(|_| ()))
+                    .help("Only exports messages older than provided duration, e.g. 12h, 30d, or 2w.")
+            )
+            .arg(
+                Arg::with_name("after")
+                    .long("after")
+                    .takes_value(true)
+                    .conflicts_with("newer_than")
+                    .validator(|value| parse_date(&value).map(|_| ()))
+                    .help("Only exports messages sent after provided date, e.g. 2024-01-31, 2024-01-31T09:00:00Z, or 1706691600.")
+            )
+            .arg(
+                Arg::with_name("before")
+                    .long("before")
+                    .takes_value(true)
+                    .conflicts_with("older_than")
+                    .validator(|value| parse_date(&value).map(|_| ()))
+                    .help("Only exports messages sent before provided date, e.g. 2024-01-31, 2024-01-31T09:00:00Z, or 1706691600.")
+            )
+            .arg(
+                Arg::with_name("since_last_run")
+                    .long("since-last-run")
+                    .conflicts_with_all(&["newer_than", "after"])
+                    .help("Only exports messages newer than the newest one previously exported from the conversation, or every message on the first run.")
+            )
+            .arg(
+                Arg::with_name("no_threads")
+                    .long("no-threads")
+                    .help("Leaves out replies in threads, only exporting top-level messages.")
+            )
+            .arg(
+                Arg::with_name("resolve_mentions")
+                    .long("resolve-mentions")
+                    .help("Replaces user and channel mentions like <@U0123456789> with @names and links with their text.")
+            )
+            .arg(
+                Arg::with_name("strip_formatting")
+                    .long("strip-formatting")
+                    .help("Removes *bold*, _italic_, ~strikethrough~, and `code` markers from message text.")
+            )
+            .arg(
+                Arg::with_name("output_file")
+                    .long("output-file")
+                    .takes_value(true)
+                    .help("Writes messages to the provided file instead of stdout.")
+            )
+            .arg(
+                Arg::with_name("sqlite")
+                    .long("sqlite")
+                    .takes_value(true)
+                    .value_name("PATH")
+                    .conflicts_with("output_file")
+                    .help("Writes messages to a messages table in the provided SQLite database instead, creating it if needed.")
+            )
+            .arg(
+                Arg::with_name("CHANNEL")
+                    .help("ID or name of the conversation to export, e.g. C0123456789, #general, or @alice.")
+                    .required(true)
+                    .index(1)
+            )
+        )
+        .subcommand(SubCommand::with_name("files")
+            .about("List or delete uploaded files.")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("list")
+                .about("List files uploaded to the workspace.")
+                .arg(
+                    Arg::with_name("user")
+                        .long("user")
+                        .takes_value(true)
+                        .help("Only lists files uploaded by provided user ID.")
+                )
+                .arg(
+                    Arg::with_name("channel")
+                        .long("channel")
+                        .takes_value(true)
+                        .help("Only lists files shared in provided conversation, e.g. C0123456789 or #general.")
+                )
+            )
+            .subcommand(SubCommand::with_name("delete")
+                .about("Delete uploaded files.")
+                .arg(
+                    Arg::with_name("FILE")
+                        .help("IDs of the files to delete.")
+                        .required(true)
+                        .multiple(true)
+                        .index(1)
+                )
+            )
+        )
+        .subcommand(SubCommand::with_name("stats")
+            .about("Summarize conversations in the workspace.")
+        )
+        .subcommand(SubCommand::with_name("users")
+            .about("List members of the workspace.")
+            .arg(
+                Arg::with_name("json")
+                    .long("json")
+                    .help("Prints users as JSON.")
+            )
+            .arg(
+                Arg::with_name("SUBSTRING")
+                    .help("Narrows results down to those whose name or real name contain provided substring.")
+                    .index(1)
+            )
+        )
+        .subcommand(SubCommand::with_name("workspaces")
+            .about("List workspaces with saved tokens, for use with --workspace.")
+        )
+        .subcommand(SubCommand::with_name("completions")
+            .about("Prints a shell completion script.")
+            .setting(AppSettings::Hidden)
+            .arg(
+                Arg::with_name("SHELL")
+                    .possible_values(&Shell::variants())
+                    .required(true)
+                    .index(1)
+            )
+        )
+        // Verbosity level
+        .arg(
+            Arg::with_name("silent")
+                .short("s")
+                .long("silent")
+                .conflicts_with_all(&["quiet", "verbose"])
+                .help("Silences all output. Confirmation prompts are skipped and answered yes."),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .short("q")
+                .multiple(true)
+                .long("quiet")
+                .conflicts_with("verbose")
+                .help("Shows less detail. -q shows less detail, -qq shows least detail, -qqq is equivalent to --silent."),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .short("v")
+                .multiple(true)
+                .long("verbose")
+                .help("Shows more detail. -v shows more detail, -vv shows most detail."),
+        )
+        .arg(
+            Arg::with_name("pretty_duration")
+                .long("pretty-duration")
+                .help("Prints how long the command took in hours, minutes, and seconds, e.g. 2m 0.5s instead of 120.500s."),
+        )
+        .arg(
+            Arg::with_name("log_format")
+                .long("log-format")
+                .possible_values(&["text", "json"])
+                .takes_value(true)
+                .default_value("text")
+                .help("Format of log messages. `json` prints one JSON object per line."),
+        )
+        // Networking
+        .arg(
+            Arg::with_name("max_retries")
+                .long("max-retries")
+                .takes_value(true)
+                .default_value("3")
+                .validator(|value| value.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()))
+                .help("Number of times to retry requests that fail due to network errors."),
+        )
+        .arg(
+            Arg::with_name("retry_on_error")
+                .long("retry-on-error")
+                .takes_value(true)
+                .multiple(true)
+                .require_delimiter(true)
+                .default_value("service_unavailable,internal_error,fatal_error,request_timeout")
+                .help("Comma-separated Slack errors to retry with backoff, up to --max-retries times. Other errors fail straight away. Rate limits are always retried."),
+        )
+        .arg(
+            Arg::with_name("max_pages")
+                .long("max-pages")
+                .takes_value(true)
+                .validator(|value| match value.parse::<u32>() {
+                    Ok(0) => Err("Must be at least 1".to_string()),
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(e.to_string()),
+                })
+                .help("Stops listing conversations after this many pages of up to 1,000, warning with the cursor to resume from. Unlimited by default."),
+        )
+        .arg(
+            Arg::with_name("concurrency")
+                .long("concurrency")
+                .takes_value(true)
+                .default_value("4")
+                .validator(|value| match value.parse::<usize>() {
+                    Ok(0) => Err("Must be at least 1".to_string()),
+                    Ok(_) => Ok(()),
+                    Err(e) => Err(e.to_string()),
+                })
+                .help("Maximum number of users and members to look up at once. Requests keep their slot while retrying, so backoff slows every lookup rather than piling more on."),
+        )
+        .arg(
+            Arg::with_name("timeout")
+                .long("timeout")
+                .takes_value(true)
+                .default_value("30")
+                .validator(|value| value.parse::<u64>().map(|_| ()).map_err(|e| e.to_string()))
+                .help("Seconds to wait for each request to Slack before giving up on it."),
+        )
+        // Formatting
+        .arg(
+            Arg::with_name("no_color")
+                .long("no-color")
+                .help("Prints without colors. Colors are also left out when stdout isn't a terminal or NO_COLOR is set."),
+        )
+        .arg(
+            Arg::with_name("locale")
+                .long("locale")
+                .takes_value(true)
+                .default_value("en")
+                .validator(|value| {
+                    Locale::from_name(&value).map(|_| ()).map_err(|_| {
+                        format!(
+                            "Unknown locale `{}`. Supported locales are: {}",
+                            value,
+                            Locale::available_names().join(", ")
+                        )
+                    })
+                })
+                .help("Locale used to format numbers, e.g. en, de, or fr."),
+        )
+        // Authentication
+        .arg(
+            Arg::with_name("token_stdin")
+                .long("token-stdin")
+                .conflicts_with("workspace")
+                .help("Reads the token from the first line of standard input instead of TOKEN. Later lines answer any confirmation prompts."),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .takes_value(true)
+                .value_name("PATH")
+                .help("Reads defaults for --workspace, --max-retries, --timeout, and --concurrency from this JSON file instead of <config dir>/tidy_slack/config.json."),
+        )
+        .arg(
+            Arg::with_name("workspace")
+                .long("workspace")
+                .takes_value(true)
+                .validator(|value| {
+                    if value.is_empty() || value.contains(std::path::is_separator) {
+                        Err(format!("`{}` isn't a valid workspace name", value))
+                    } else {
+                        Ok(())
+                    }
+                })
+                .help("Reads the token from <config dir>/tidy_slack/<WORKSPACE>.token instead of TOKEN. See `workspaces`."),
+        )
+        .arg(
+            Arg::with_name("cookie")
+                .long("cookie")
+                .env("SLACK_COOKIE")
+                .takes_value(true)
+                .help("Value of the `d` cookie. Required when TOKEN contains a browser session token (xoxc-...)."),
+        )
+        .arg(
+            Arg::with_name("api_base")
+                .long("api-base")
+                .env("SLACK_API_BASE")
+                .takes_value(true)
+                .value_name("URL")
+                .default_value(slack::API_BASE)
+                .validator(|value| match Url::parse(&value) {
+                    Ok(url) if url.scheme() == "http" || url.scheme() == "https" => Ok(()),
+                    Ok(_) => Err("Must be an http or https URL".to_string()),
+                    Err(e) => Err(e.to_string()),
+                })
+                .help("URL that Slack's API methods are under, e.g. a mock server or proxy."),
+        )
+}
+
+/// Checks that `value` is one of `TYPES`, suggesting the closest one if it looks like a typo.
+fn validate_type(value: String) -> Result<(), String> {
+    if value == "all" || TYPES.contains(&value.as_str()) {
+        return Ok(());
+    }
+
+    let suggestion = TYPES
+        .iter()
+        .map(|kind| (edit_distance(&value, kind), kind))
+        .min()
+        .filter(|(distance, _)| *distance <= 3)
+        .map(|(_, kind)| format!(" Did you mean `{}`?", kind))
+        .unwrap_or_default();
+    Err(format!(
+        "Unknown type `{}`.{} Valid types are {}, or all.",
+        value,
+        suggestion,
+        TYPES.join(", ")
+    ))
+}
+
+/// Types listed with `--types`, in the order of `TYPES`, with `all` standing in for each of them.
+fn expand_types(types: &[String]) -> Vec<String> {
+    TYPES
+        .iter()
+        .filter(|kind| {
+            types
+                .iter()
+                .any(|listed| listed == "all" || listed == *kind)
+        })
+        .map(|kind| kind.to_string())
+        .collect()
+}
+
+/// Orders conversations like their `Ord` impl, except that types are grouped in `type_order`,
+/// followed by any types left out of it.
+fn cmp_by_type_order(
+    a: &NormalizedConversation,
+    b: &NormalizedConversation,
+    type_order: &[&str],
+) -> Ordering {
+    let rank = |conversation: &NormalizedConversation| {
+        type_order
+            .iter()
+            .position(|kind| *kind == conversation.type_name())
+            .unwrap_or(type_order.len())
+    };
+    rank(a).cmp(&rank(b)).then_with(|| a.cmp(b))
+}
+
+/// Most single character edits between the normalized names of channels flagged as duplicates.
+const DUPLICATE_DISTANCE: usize = 1;
+
+/// Flags channels whose normalized names are within `DUPLICATE_DISTANCE` of each other,
+/// ignoring separators so `#dev-ops` and `#devops` match,
+/// and moves the rest of each group up to follow its first member.
+fn group_duplicates(conversations: Vec<NormalizedConversation>) -> Vec<NormalizedConversation> {
+    let keys = conversations
+        .iter()
+        .map(|conversation| match conversation.type_identifier.as_str() {
+            "#" | "!" => conversation.normalized_names.first().map(|name| {
+                name.chars()
+                    .filter(|c| !"-_.".contains(*c))
+                    .collect::<String>()
+            }),
+            _ => None,
+        })
+        .collect::<Vec<Option<String>>>();
+
+    // Each conversation's group is the lowest index it's transitively similar to
+    let mut groups = (0..conversations.len()).collect::<Vec<usize>>();
+    for (i, a) in keys.iter().enumerate() {
+        for (j, b) in keys.iter().enumerate().skip(i + 1) {
+            if let (Some(a), Some(b)) = (a, b) {
+                let similar = a.len().abs_diff(b.len()) <= DUPLICATE_DISTANCE
+                    && edit_distance(a, b) <= DUPLICATE_DISTANCE;
+                if similar {
+                    let (from, to) = (groups[i].max(groups[j]), groups[i].min(groups[j]));
+                    for group in &mut groups {
+                        if *group == from {
+                            *group = to;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    let names = conversations
+        .iter()
+        .map(NormalizedConversation::display_names)
+        .collect::<Vec<String>>();
+    let mut conversations = conversations.into_iter().map(Some).collect::<Vec<_>>();
+    let mut grouped = vec![];
+    for i in 0..conversations.len() {
+        for j in i..conversations.len() {
+            if groups[j] != groups[i] {
+                continue;
+            }
+            if let Some(mut conversation) = conversations[j].take() {
+                conversation.similar_to = (0..names.len())
+                    .filter(|&k| k != j && groups[k] == groups[j])
+                    .map(|k| names[k].clone())
+                    .collect();
+                grouped.push(conversation);
+            }
+        }
+    }
+    grouped
+}
+
+/// Number of single character insertions, deletions, or substitutions that turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<char>>();
+    let mut previous = (0..=b.len()).collect::<Vec<usize>>();
+    for (i, a) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + if a == *b { 0 } else { 1 };
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+fn log_level(options: &ArgMatches<'_>) -> LevelFilter {
+    // Default log level is Info
+    // --silent switches level to Off
+    // -v, --verbose increases level to debug
+    // -vv increases level to Trace
+    // -q, --quiet decreases level to Warn
+    // -qq decreases level to Error
+    // -qqq decreases level to Off
+    if options.is_present("silent") {
+        LevelFilter::Off
+    } else {
+        match options.occurrences_of("verbose") {
+            0 => match options.occurrences_of("quiet") {
+                3..=u64::MAX => LevelFilter::Off,
+                2 => LevelFilter::Error,
+                1 => LevelFilter::Warn,
+                0 => LevelFilter::Info, // Default
+            },
+            1 => LevelFilter::Debug,
+            2..=u64::MAX => LevelFilter::Trace,
+        }
+    }
+}
+
+fn completions(options: &ArgMatches<'_>) {
+    let shell = value_t_or_exit!(options, "SHELL", Shell);
+    build_cli().gen_completions_to(env!("CARGO_PKG_NAME"), shell, &mut io::stdout());
+}
+
+async fn ls(
+    slack: &dyn SlackApi,
+    types: [&str; 4],
+    options: Option<&ArgMatches<'_>>,
+    filter: LevelFilter,
+    locale: Locale,
+    concurrency: usize,
+) {
+    // -q and -qq only get the bar itself, without the prefix and message lines
+    let lines = if filter < LevelFilter::Info {
+        ""
+    } else {
+        "\n           {prefix}\n           {msg}"
+    };
+    // The rate and ETA are meaningless until the number of conversations is known
+    let style = |eta: &str| {
+        ProgressStyle::default_bar()
+            .template(&format!(
+                "{{elapsed_precise}} [{{bar:40}}] {{pos:>7}}/{{len:7}}{}{}",
+                eta, lines
+            ))
+            .progress_chars("=> ")
+    };
+
+    let length = 4;
+    let main_progress = ProgressBar::new(length);
+    main_progress.set_style(style(""));
+
+    main_progress.set_prefix("Retrieving all conversations...");
+
+    let enabled_types;
+    let mut substrings = vec![];
+    let mut match_all = false;
+    let mut exclude_archived = false;
+    let mut only_member = false;
+    let mut exclude_bots = false;
+    let mut exclude_deleted_users = false;
+    let mut search_topics = false;
+    let mut match_normalized = false;
+    let mut excludes = vec![];
+    let mut min_members = None;
+    let mut max_members = None;
+    let mut filter_expr = None;
+    let mut head = None;
+    let mut tail = None;
+    let mut sample_size = None;
+    let mut seed = None;
+    let mut type_order = vec![];
+    let mut flag_duplicates = false;
+    let mut team_id = None;
+    let mut start_cursor = None;
+    let mut total_limit = None;
+    let mut stream = false;
+    let mut table = false;
+    let mut csv = false;
+    let mut fields = None;
+    let mut json = false;
+    let mut json_lines = false;
+    let mut print_ids = false;
+    let mut null = false;
+    let mut pretty = false;
+    let mut include_members = false;
+    let mut show_unread = false;
+    let mut show_creator = false;
+    let mut show_shared = false;
+    let mut show_parent = false;
+    let mut show_url = false;
+    let mut profile_field = ProfileField::Name;
+    let mut output_file = None;
+    let mut no_pager = false;
+    if let Some(options) = options {
+        enabled_types = if let Some(specified_types) = options.values_of_lossy("types") {
+            expand_types(&specified_types)
+        } else {
+            types
+                .iter()
+                .filter(|kind| !(options.is_present("no_im") && **kind == "im"))
+                .filter(|kind| !(options.is_present("no_mpim") && **kind == "mpim"))
+                .map(|s| s.to_string())
+                .collect()
+        };
+        if options.is_present("exclude_archived") {
+            exclude_archived = true;
+        }
+        if options.is_present("only_member") {
+            only_member = true;
+        }
+        if options.is_present("exclude_bots") {
+            exclude_bots = true;
+        }
+        if options.is_present("exclude_deleted_users") {
+            exclude_deleted_users = true;
+        }
+        if let Some(provided_substrings) = options.values_of("SUBSTRING") {
+            substrings = provided_substrings.collect();
+        }
+        if options.value_of("match") == Some("all") {
+            match_all = true;
+        }
+        if options.is_present("search_topics") {
+            search_topics = true;
+        }
+        if options.is_present("normalized") {
+            match_normalized = true;
+        }
+        if let Some(provided_excludes) = options.values_of("exclude") {
+            excludes = provided_excludes.collect();
+        }
+        if options.is_present("min_members") {
+            min_members = Some(value_t_or_exit!(options, "min_members", u64));
+        }
+        if options.is_present("max_members") {
+            max_members = Some(value_t_or_exit!(options, "max_members", u64));
+        }
+        filter_expr = options
+            .value_of("filter_expr")
+            .map(|expr| Expr::parse(expr).unwrap());
+        if options.is_present("head") {
+            head = Some(value_t_or_exit!(options, "head", usize));
+        }
+        if options.is_present("tail") {
+            tail = Some(value_t_or_exit!(options, "tail", usize));
+        }
+        if options.is_present("sample") {
+            sample_size = Some(value_t_or_exit!(options, "sample", usize));
+        }
+        if options.is_present("seed") {
+            seed = Some(value_t_or_exit!(options, "seed", u64));
+        }
+        if options.is_present("flag_duplicates") {
+            flag_duplicates = true;
+        }
+        if let Some(provided_type_order) = options.values_of("type_order") {
+            type_order = provided_type_order.collect();
+        }
+        team_id = options.value_of("team_id");
+        start_cursor = options.value_of("start_cursor");
+        if options.is_present("limit_total") {
+            total_limit = Some(value_t_or_exit!(options, "limit_total", usize));
+        }
+        if options.is_present("stream") {
+            stream = true;
+        }
+        match options.value_of("format") {
+            Some("table") => table = true,
+            Some("csv") => csv = true,
+            _ => {}
+        }
+        fields = options
+            .values_of("fields")
+            .map(|fields| fields.collect::<Vec<&str>>());
+        if options.is_present("json") {
+            json = true;
+        }
+        if options.is_present("json_lines") {
+            json_lines = true;
+            stream = true;
+        }
+        if options.is_present("print_ids") {
+            print_ids = true;
+        }
+        if options.is_present("null") {
+            null = true;
+        }
+        if options.is_present("pretty") {
+            pretty = true;
+        }
+        if options.is_present("include_members") {
+            include_members = true;
+        }
+        if options.is_present("show_unread") {
+            show_unread = true;
+        }
+        if options.is_present("show_creator") {
+            show_creator = true;
+        }
+        if options.is_present("show_shared") {
+            show_shared = true;
+        }
+        if options.is_present("show_parent") {
+            show_parent = true;
+        }
+        if options.is_present("show_url") {
+            show_url = true;
+        }
+        profile_field = match options.value_of("profile_field") {
+            Some("real_name") => ProfileField::RealName,
+            Some("display_name") => ProfileField::DisplayName,
+            Some("email") => ProfileField::Email,
+            _ => ProfileField::Name,
+        };
+        output_file = options.value_of("output_file");
+        if options.is_present("no_pager") {
+            no_pager = true;
+        }
+    } else {
+        enabled_types = types.to_vec().iter().map(|s| s.to_string()).collect();
+    };
+
+    // Links are built from the workspace's own URL, e.g. https://example.slack.com/
+    let workspace_url = if show_url || fields.as_ref().is_some_and(|f| f.contains(&"url")) {
+        Some(slack.test_auth().await.unwrap().url)
+    } else {
+        None
+    };
+    let workspace_url = workspace_url.as_deref();
+
+    let mut out: Box<dyn Write> = if let Some(path) = output_file {
+        // Colors would only show up as escape codes in the file
+        colored::control::set_override(false);
+        Box::new(BufWriter::new(File::create(path).unwrap()))
+    } else {
+        Box::new(io::stdout())
+    };
+
+    // --silent shouldn't draw anything,
+    // and streamed output goes straight to stdout
+    // where a progress bar would only get in the way
+    if stream || filter == LevelFilter::Off {
+        main_progress.set_draw_target(ProgressDrawTarget::hidden());
+    }
+
+    main_progress.set_prefix("Retrieving and normalizing conversations...");
+    let matches = |convo: &NormalizedConversation| {
+        let contains = |term: &&str| {
+            convo.contains(term) || (match_normalized && convo.contains_normalized(term))
+        };
+        convo.matches_terms(&substrings, match_all, search_topics, match_normalized)
+            && !excludes.iter().any(contains)
+            && convo.has_members_between(min_members, max_members)
+            && filter_expr.as_ref().is_none_or(|expr| expr.matches(convo))
+            && (!only_member || convo.is_member)
+            && !(exclude_bots && convo.is_bot)
+            && !(exclude_deleted_users && convo.is_deleted)
+    };
+
+    // Only conversations belong in output meant for other programs
+    let machine_readable = json_lines || print_ids;
+    if stream && !machine_readable {
+        print_header(&mut out, &substrings, match_all, search_topics).unwrap();
+    }
+
+    let mut counts = HashMap::new();
+    let mut conversations = vec![];
+    let main_progress = &main_progress;
+
+    // The next page is retrieved while conversations from the current one are normalized,
+    // so on workspaces with several pages (over 1,000 conversations)
+    // listing takes about as long as normalizing alone rather than both back to back
+    let started = Instant::now();
+    let (sender, receiver) = mpsc::channel(1);
+    let retrieve = async {
+        let pages =
+            slack.conversation_pages(&enabled_types, exclude_archived, team_id, start_cursor);
+        let pages = report_pages(pages, main_progress);
+        let pages = match total_limit {
+            Some(limit) => limit_total(pages, limit),
+            None => pages,
+        };
+        let retrieved = pages.map(Ok).forward(sender).await;
+        // The rate and ETA can be shown now that the total is known
+        main_progress.inc(1);
+        main_progress.set_style(style(" {per_sec} ETA {eta}"));
+        (retrieved, started.elapsed())
+    };
+
+    // Normalizing looks up users and members,
+    // so up to `concurrency` conversations are normalized at once
+    let mut normalized = receiver
+        .flat_map(|page: Result<Vec<Conversation>, Box<dyn Error>>| {
+            let page = page.unwrap();
+            main_progress.inc_length(page.len() as u64);
+            stream::iter(page)
+        })
+        .map(|conversation| async move {
+            let mut conversation = normalize_conversation(
+                slack,
+                conversation,
+                include_members,
+                profile_field,
+                main_progress,
+            )
+            .await;
+            conversation.show_shared = show_shared;
+            conversation.url = workspace_url.map(|base| archive_url(base, &conversation.id));
+            if show_unread {
+                check_unread(slack, &mut conversation, main_progress).await;
+            }
+            if show_creator {
+                resolve_creator(slack, &mut conversation, profile_field, main_progress).await;
+            }
+            if show_parent {
+                resolve_parent(slack, &mut conversation, main_progress).await;
+            }
+            conversation
+        })
+        .buffered(concurrency);
+    let normalize = async {
+        while let Some(mut conversation) = normalized.next().await {
+            main_progress.inc(1);
+
+            if stream {
+                if matches(&conversation) {
+                    conversation.names.sort_unstable();
+                    if json_lines {
+                        print_json_line(&mut out, &conversation).unwrap();
+                    } else if print_ids {
+                        print_id(&mut out, &conversation, null).unwrap();
+                    } else {
+                        print_conversation(&mut out, &conversation, fields.as_deref()).unwrap();
+                    }
+                    *counts.entry(conversation.type_name()).or_insert(0) += 1;
+                }
+            } else {
+                conversations.push(conversation);
+            }
+        }
+        started.elapsed()
+    };
+    let ((retrieved, retrieving), normalizing) = future::join(retrieve, normalize).await;
+    retrieved.unwrap();
+    // Retrieval and normalization overlap, so both are timed from the start
+    let mut timings = vec![
+        ("Retrieving conversations", retrieving),
+        ("Normalizing conversations and resolving users", normalizing),
+    ];
+
+    if stream {
+        trace_timings(&timings, locale);
+        main_progress.finish_and_clear();
+        if counts.is_empty() && !machine_readable {
+            print_empty(&mut out, &substrings, match_all).unwrap();
+        } else if filter != LevelFilter::Off && !machine_readable {
+            print_footer(&mut out, &counts, locale).unwrap();
+        }
+        out.flush().unwrap();
+        return;
+    }
+
+    let filtering = Instant::now();
+    if !substrings.is_empty()
+        || !excludes.is_empty()
+        || min_members.is_some()
+        || max_members.is_some()
+        || filter_expr.is_some()
+        || only_member
+        || exclude_bots
+        || exclude_deleted_users
+    {
+        if substrings.is_empty() {
+            main_progress.set_prefix("Filtering conversations...");
+        } else {
+            main_progress.set_prefix(&format!(
+                "Filtering conversations down to those that contain {}...",
+                describe_terms(&substrings, match_all)
+            ));
+        }
+
+        conversations = conversations
+            .into_iter()
+            .filter(|convo| matches(convo))
+            .collect::<Vec<NormalizedConversation>>();
+    }
+
+    timings.push(("Filtering", filtering.elapsed()));
+
+    main_progress.inc(1);
+    main_progress.set_prefix("Sorting names in multi-person DMs...");
+    let sorting = Instant::now();
+
+    for conversation in &mut conversations {
+        conversation.names.sort_unstable();
+    }
+
+    main_progress.inc(1);
+    main_progress.set_prefix("Sorting conversations by type and name...");
+
+    conversations.sort_unstable_by(|a, b| cmp_by_type_order(a, b, &type_order));
+    if flag_duplicates {
+        conversations = group_duplicates(conversations);
+    }
+    timings.push(("Sorting", sorting.elapsed()));
+    trace_timings(&timings, locale);
+
+    if let Some(head) = head {
+        conversations.truncate(head);
+    }
+    if let Some(tail) = tail {
+        conversations.drain(..conversations.len().saturating_sub(tail));
+    }
+    if let Some(sample_size) = sample_size {
+        conversations = sample(conversations, sample_size, seed);
+    }
+
+    main_progress.inc(1);
+    main_progress.finish_and_clear();
+
+    // Streamed output is already printed as it arrives,
+    // so only complete listings go through the pager
+    if output_file.is_none() && !no_pager && io::stdout().is_terminal() {
+        if let Some(pager) = Pager::spawn() {
+            out = Box::new(pager);
+        }
+    }
+
+    if json {
+        if pretty {
+            serde_json::to_writer_pretty(&mut out, &conversations).unwrap();
+        } else {
+            serde_json::to_writer(&mut out, &conversations).unwrap();
+        }
+        writeln!(out).unwrap();
+    } else if print_ids {
+        for conversation in &conversations {
+            print_id(&mut out, conversation, null).unwrap();
+        }
+    } else if csv {
+        print_csv(&mut out, &conversations, fields.as_deref()).unwrap();
+    } else if conversations.is_empty() {
+        print_empty(&mut out, &substrings, match_all).unwrap();
+    } else {
+        print_header(&mut out, &substrings, match_all, search_topics).unwrap();
+
+        for conversation in &conversations {
+            *counts.entry(conversation.type_name()).or_insert(0) += 1;
+        }
+
+        if table {
+            print_table(&mut out, &conversations, fields.as_deref()).unwrap();
+        } else {
+            for conversation in conversations {
+                print_conversation(&mut out, &conversation, fields.as_deref()).unwrap();
+            }
+        }
+
+        if filter != LevelFilter::Off {
+            print_footer(&mut out, &counts, locale).unwrap();
+        }
+    }
+
+    out.flush().unwrap();
+}
+
+#[derive(Serialize, Debug)]
+struct NormalizedConversation {
+    id: String,
+    type_identifier: String,
+    names: Vec<String>,
+    #[serde(skip)]
+    normalized_names: Vec<String>,
+    created: u64,
+    is_archived: bool,
+    is_deleted: bool,
+    is_bot: bool,
+    is_member: bool,
+    is_general: bool,
+    is_shared: bool,
+    is_ext_shared: bool,
+    is_org_shared: bool,
+    #[serde(skip)]
+    show_shared: bool,
+    topic: Option<String>,
+    purpose: Option<String>,
+    member_ids: Vec<String>,
+    num_members: Option<u64>,
+    last_read: Option<String>,
+    has_unread: Option<bool>,
+    creator_id: Option<String>,
+    creator: Option<String>,
+    is_creator_deleted: bool,
+    parent_id: Option<String>,
+    parent: Option<String>,
+    url: Option<String>,
+    similar_to: Vec<String>,
+}
+// Ordered by type, then names, then ID to break ties
+impl Ord for NormalizedConversation {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.type_identifier
+            .cmp(&other.type_identifier)
+            .then_with(|| self.names.cmp(&other.names))
+            .then_with(|| self.id.cmp(&other.id))
+    }
+}
+impl PartialOrd for NormalizedConversation {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl PartialEq for NormalizedConversation {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for NormalizedConversation {}
+impl NormalizedConversation {
+    /// Whether any of the conversation's names contain `substring`.
+    fn contains(&self, substring: &str) -> bool {
+        self.names.iter().any(|name| name.contains(substring))
+    }
+
+    /// Whether any of Slack's normalized names, e.g. `jose` for `josé`, contain `substring`.
+    fn contains_normalized(&self, substring: &str) -> bool {
+        self.normalized_names
+            .iter()
+            .any(|name| name.contains(substring))
+    }
+
+    /// Icon and color representing the conversation's state.
+    fn icon_and_color(&self) -> (&'static str, Color) {
+        if self.is_deleted {
+            ("🗑", Color::Red)
+        } else if self.is_archived {
+            ("🗄", Color::Yellow)
+        } else if !self.is_member {
+            ("🚪", Color::BrightBlack)
+        } else {
+            ("🗒", Color::White)
+        }
+    }
+
+    /// Notes appended to names, e.g. ` (unread)` or ` (created by @alice)`.
+    fn annotations(&self) -> String {
+        let mut annotations = String::new();
+        if self.has_unread == Some(true) {
+            annotations.push_str(" (unread)");
+        }
+        if self.show_shared {
+            if self.is_ext_shared {
+                annotations.push_str(" (shared externally)");
+            } else if self.is_org_shared {
+                annotations.push_str(" (shared across organization)");
+            } else if self.is_shared {
+                annotations.push_str(" (shared)");
+            }
+        }
+        if let Some(parent) = &self.parent {
+            annotations.push_str(&format!(" (in {})", parent));
+        }
+        if !self.similar_to.is_empty() {
+            annotations.push_str(&format!(" (similar to {})", self.similar_to.join(", ")));
+        }
+        if let Some(creator) = &self.creator {
+            annotations.push_str(&format!(" (created by @{}", creator));
+            if self.is_creator_deleted {
+                annotations.push_str(", deactivated");
+            }
+            annotations.push(')');
+        }
+        annotations
+    }
+
+    /// Names prefixed with the type identifier, e.g. `&alice, &bob`.
+    fn display_names(&self) -> String {
+        format!(
+            "{}{}",
+            self.type_identifier,
+            self.names.join(&format!(", {}", self.type_identifier))
+        )
+    }
+
+    /// Value of `field`, one of `FIELDS`, as printed in listings.
+    fn field(&self, field: &str) -> String {
+        match field {
+            "id" => self.id.clone(),
+            "type" => self.type_name().to_string(),
+            "name" => format!("{}{}", self.display_names(), self.annotations()),
+            "created" => self.created.to_string(),
+            "members" => self
+                .num_members
+                .map_or_else(|| "-".to_string(), |num_members| num_members.to_string()),
+            "archived" => if self.is_archived {
+                "archived"
+            } else {
+                "active"
+            }
+            .to_string(),
+            "url" => self.url.clone().unwrap_or_else(|| "-".to_string()),
+            _ => panic!("Unsupported field: {}", field),
+        }
+    }
+
+    /// Conversation type as accepted by `--types`.
+    fn type_name(&self) -> &'static str {
+        match self.type_identifier.as_str() {
+            "#" => "public_channel",
+            "!" => "private_channel",
+            "&" => "mpim",
+            "@" => "im",
+            _ => "unknown",
+        }
+    }
+
+    /// Whether the conversation has between `min` and `max` members, inclusive.
+    /// Conversations without a member count only match when neither is provided.
+    fn has_members_between(&self, min: Option<u64>, max: Option<u64>) -> bool {
+        match self.num_members {
+            Some(num_members) => {
+                min.is_none_or(|min| num_members >= min) && max.is_none_or(|max| num_members <= max)
+            }
+            None => min.is_none() && max.is_none(),
+        }
+    }
+
+    /// Whether the conversation's topic or purpose contain `substring`.
+    fn describes(&self, substring: &str) -> bool {
+        self.topic
+            .iter()
+            .chain(self.purpose.iter())
+            .any(|text| text.contains(substring))
+    }
+
+    /// Whether the conversation contains any of `terms`, or all of them with `match_all`,
+    /// also looking at its topic and purpose with `search_topics`
+    /// and its normalized names with `match_normalized`.
+    /// Every conversation matches when there are no terms.
+    fn matches_terms(
+        &self,
+        terms: &[&str],
+        match_all: bool,
+        search_topics: bool,
+        match_normalized: bool,
+    ) -> bool {
+        let matches = |term: &&str| {
+            self.contains(term)
+                || (match_normalized && self.contains_normalized(term))
+                || (search_topics && self.describes(term))
+        };
+        if match_all {
+            terms.iter().all(matches)
+        } else {
+            terms.is_empty() || terms.iter().any(matches)
+        }
+    }
+}
+
+impl Subject for NormalizedConversation {
+    fn type_name(&self) -> &str {
+        NormalizedConversation::type_name(self)
+    }
+
+    fn is_archived(&self) -> bool {
+        self.is_archived
+    }
+
+    fn members(&self) -> Option<u64> {
+        self.num_members
+    }
+
+    fn names(&self) -> &[String] {
+        &self.names
+    }
+}
+
+async fn normalize_conversation(
+    slack: &dyn SlackApi,
+    conversation: Conversation,
+    include_members: bool,
+    profile_field: ProfileField,
+    progress: &ProgressBar,
+) -> NormalizedConversation {
+    match conversation {
+        Conversation::PublicChannel(convo) => {
+            progress.set_message(&format!("Normalizing #{}", convo.name));
+            NormalizedConversation {
+                id: convo.id,
+                type_identifier: "#".to_string(),
+                names: vec![convo.name],
+                normalized_names: vec![convo.name_normalized],
+                created: convo.created,
+                is_archived: convo.is_archived,
+                is_deleted: false,
+                is_bot: false,
+                is_member: convo.is_member,
+                is_general: convo.is_general,
+                is_shared: convo.is_shared,
+                is_ext_shared: convo.is_ext_shared,
+                is_org_shared: convo.is_org_shared,
+                show_shared: false,
+                topic: convo.topic.value,
+                purpose: convo.purpose.value,
+                member_ids: vec![],
+                num_members: Some(convo.num_members),
+                last_read: convo.last_read,
+                has_unread: None,
+                creator_id: Some(convo.creator),
+                creator: None,
+                is_creator_deleted: false,
+                parent_id: convo.parent_conversation,
+                parent: None,
+                url: None,
+                similar_to: vec![],
+            }
+        }
+        Conversation::Mpim(convo) => {
+            let mut member_ids = vec![];
+            if include_members {
+                progress.set_message("Retrieving members of conversation with multiple members");
+                member_ids = slack.list_members(&convo.id).await.unwrap();
+                member_ids.sort_unstable();
+                progress.tick();
+            }
+            progress.set_message("Normalizing conversation with multiple members");
+            NormalizedConversation {
+                id: convo.id,
+                type_identifier: "&".to_string(),
+                names: mpim_names(&convo.name),
+                normalized_names: mpim_names(&convo.name_normalized),
+                created: convo.created,
+                is_archived: convo.is_archived,
+                is_deleted: false,
+                is_bot: false,
+                is_member: convo.is_member,
+                is_general: convo.is_general,
+                is_shared: convo.is_shared,
+                is_ext_shared: convo.is_ext_shared,
+                is_org_shared: convo.is_org_shared,
+                show_shared: false,
+                topic: convo.topic.value,
+                purpose: convo.purpose.value,
+                member_ids,
+                num_members: None,
+                last_read: convo.last_read,
+                has_unread: None,
+                creator_id: None,
+                creator: None,
+                is_creator_deleted: false,
+                parent_id: convo.parent_conversation,
+                parent: None,
+                url: None,
+                similar_to: vec![],
+            }
+        }
+        Conversation::PrivateChannel(convo) => {
+            progress.set_message(&format!("Normalizing private channel #{}", convo.name));
+            NormalizedConversation {
+                id: convo.id,
+                type_identifier: "!".to_string(),
+                names: vec![convo.name],
+                normalized_names: vec![convo.name_normalized],
+                created: convo.created,
+                is_archived: convo.is_archived,
+                is_deleted: false,
+                is_bot: false,
+                is_member: convo.is_member,
+                is_general: convo.is_general,
+                is_shared: convo.is_shared,
+                is_ext_shared: convo.is_ext_shared,
+                is_org_shared: convo.is_org_shared,
+                show_shared: false,
+                topic: convo.topic.value,
+                purpose: convo.purpose.value,
+                member_ids: vec![],
+                num_members: convo.num_members,
+                last_read: convo.last_read,
+                has_unread: None,
+                creator_id: Some(convo.creator),
+                creator: None,
+                is_creator_deleted: false,
+                parent_id: convo.parent_conversation,
+                parent: None,
+                url: None,
+                similar_to: vec![],
+            }
+        }
+        Conversation::Im(convo) => {
+            progress.set_message(&format!("Retrieving metadata for user {}", convo.user));
+            let user = slack.get_user(&convo.user).await.unwrap();
+            let is_bot = user.is_bot();
+            let name = user.profile_field(profile_field);
+            progress.tick();
+            progress.set_message(&format!("Normalizing conversation with @{}", name));
+            NormalizedConversation {
+                id: convo.id,
+                type_identifier: "@".to_string(),
+                names: vec![name.clone()],
+                normalized_names: vec![name],
+                created: convo.created,
+                is_archived: convo.is_archived,
+                is_deleted: convo.is_user_deleted,
+                is_bot,
+                is_member: true,
+                is_general: false,
+                is_shared: false,
+                is_ext_shared: false,
+                is_org_shared: convo.is_org_shared,
+                show_shared: false,
+                topic: None,
+                purpose: None,
+                member_ids: vec![convo.user],
+                num_members: None,
+                last_read: None,
+                has_unread: None,
+                creator_id: None,
+                creator: None,
+                is_creator_deleted: false,
+                parent_id: None,
+                parent: None,
+                url: None,
+                similar_to: vec![],
+            }
+        }
+    }
+}
+
+/// Member names from an MPIM's name, e.g. `alice` and `bob` from `mpdm-alice--bob-1`.
+/// Names in any other format are kept whole rather than guessed at.
+fn mpim_names(name: &str) -> Vec<String> {
+    let names = name
+        .strip_prefix("mpdm-")
+        .and_then(|names| names.rsplit_once('-'))
+        .filter(|(_, suffix)| !suffix.is_empty() && suffix.chars().all(|c| c.is_ascii_digit()))
+        .map(|(names, _)| names.split("--").collect::<Vec<&str>>());
+    match names {
+        Some(names) if !names.iter().any(|name| name.is_empty()) => {
+            names.iter().map(|name| name.to_string()).collect()
+        }
+        _ => vec![name.to_string()],
+    }
+}
+
+/// Sets who created `conversation`, if it's a channel.
+/// Falls back to the creator's ID when Slack can't find them.
+async fn resolve_creator(
+    slack: &dyn SlackApi,
+    conversation: &mut NormalizedConversation,
+    profile_field: ProfileField,
+    progress: &ProgressBar,
+) {
+    if let Some(creator_id) = &conversation.creator_id {
+        progress.set_message(&format!("Retrieving metadata for user {}", creator_id));
+        match slack.get_user(creator_id).await {
+            Ok(user) => {
+                conversation.is_creator_deleted = user.is_deleted();
+                conversation.creator = Some(user.profile_field(profile_field));
+            }
+            Err(error) => {
+                debug!("Couldn't retrieve creator {}: {}", creator_id, error);
+                conversation.creator = Some(creator_id.clone());
+            }
+        }
+        progress.tick();
+    }
+}
+
+/// Sets the name of the conversation `conversation` belongs to, if it has one.
+/// Falls back to the parent's ID when Slack can't find it.
+async fn resolve_parent(
+    slack: &dyn SlackApi,
+    conversation: &mut NormalizedConversation,
+    progress: &ProgressBar,
+) {
+    if let Some(parent_id) = &conversation.parent_id {
+        progress.set_message(&format!("Retrieving parent conversation {}", parent_id));
+        conversation.parent = Some(match slack.get_conversation(parent_id).await {
+            Ok(Conversation::PublicChannel(parent)) => format!("#{}", parent.name),
+            Ok(Conversation::PrivateChannel(parent)) => format!("!{}", parent.name),
+            Ok(Conversation::Mpim(parent)) => format!("&{}", parent.name),
+            Ok(Conversation::Im(parent)) => parent.id,
+            Err(error) => {
+                debug!("Couldn't retrieve parent {}: {}", parent_id, error);
+                parent_id.clone()
+            }
+        });
+        progress.tick();
+    }
+}
+
+/// Sets whether `conversation` has messages newer than the last one read,
+/// leaving it unset for conversations Slack doesn't track reads in for the user
+/// or whose latest message can't be retrieved.
+async fn check_unread(
+    slack: &dyn SlackApi,
+    conversation: &mut NormalizedConversation,
+    progress: &ProgressBar,
+) {
+    if let Some(last_read) = &conversation.last_read {
+        progress.set_message(&format!(
+            "Checking for unread messages in {}",
+            conversation.id
+        ));
+        match slack.get_latest_message(&conversation.id).await {
+            Ok(latest) => conversation.has_unread = Some(is_unread(last_read, latest.as_ref())),
+            Err(error) => debug!(
+                "Couldn't retrieve the latest message in {}: {}",
+                conversation.id, error
+            ),
+        }
+        progress.tick();
+    }
+}
+
+/// Whether `latest` was sent after `last_read`.
+fn is_unread(last_read: &str, latest: Option<&Message>) -> bool {
+    let timestamp = |ts: &str| ts.parse::<f64>().unwrap_or(0.0);
+    latest.is_some_and(|message| timestamp(&message.ts) > timestamp(last_read))
+}
+
+/// Terms quoted and joined by `and` or `or`, e.g. `` `proj` and `2024` ``.
+fn describe_terms(terms: &[&str], match_all: bool) -> String {
+    terms
+        .iter()
+        .map(|term| format!("`{}`", term))
+        .collect::<Vec<String>>()
+        .join(if match_all { " and " } else { " or " })
+}
+
+/// Link that opens `conversation` in the workspace at `base`, e.g. `https://example.slack.com/`.
+fn archive_url(base: &str, conversation: &str) -> String {
+    format!("{}/archives/{}", base.trim_end_matches('/'), conversation)
+}
+
+/// Says nothing was listed, which would otherwise look like a header missing its listing.
+fn print_empty(out: &mut dyn Write, substrings: &[&str], match_all: bool) -> io::Result<()> {
+    if substrings.is_empty() {
+        writeln!(out, "No conversations found")
+    } else {
+        writeln!(
+            out,
+            "No conversations match {}",
+            describe_terms(substrings, match_all)
+        )
+    }
+}
+
+fn print_header(
+    out: &mut dyn Write,
+    substrings: &[&str],
+    match_all: bool,
+    search_topics: bool,
+) -> io::Result<()> {
+    if substrings.is_empty() {
+        writeln!(out, "All conversations you have access to:")
+    } else if search_topics {
+        writeln!(
+            out,
+            "All conversations with names, topics, or purposes that contain {} that you have access to:",
+            describe_terms(substrings, match_all)
+        )
+    } else {
+        writeln!(
+            out,
+            "All conversations with names that contain {} that you have access to:",
+            describe_terms(substrings, match_all)
+        )
+    }
+}
+
+fn print_conversation(
+    out: &mut dyn Write,
+    conversation: &NormalizedConversation,
+    fields: Option<&[&str]>,
+) -> io::Result<()> {
+    let (icon, color) = conversation.icon_and_color();
+    let line = match fields {
+        Some(fields) => fields
+            .iter()
+            .map(|field| conversation.field(field))
+            .collect::<Vec<String>>()
+            .join(" "),
+        None => format!(
+            "{}: {}{}{}",
+            conversation.id.bold(),
+            conversation.display_names(),
+            conversation.annotations(),
+            conversation
+                .url
+                .as_ref()
+                .map(|url| format!(" {}", url))
+                .unwrap_or_default()
+        ),
+    };
+    writeln!(out, "{}", format!("{} {}", icon, line).color(color))
+}
+
+/// Stops `pages` once `limit` conversations have been retrieved, truncating the last page to fit,
+/// so no more pages are requested than needed.
+/// Reports each page of `pages` to `progress` as it's retrieved,
+/// since Slack doesn't say how many pages there are.
+fn report_pages<'a>(
+    pages: LocalBoxStream<'a, Result<Vec<Conversation>, Box<dyn Error>>>,
+    progress: &'a ProgressBar,
+) -> LocalBoxStream<'a, Result<Vec<Conversation>, Box<dyn Error>>> {
+    progress.set_message("Retrieving conversations");
+    progress.tick();
+    pages
+        .enumerate()
+        .map(move |(index, page)| {
+            progress.set_message(&format!("Retrieved {} pages of conversations", index + 1));
+            progress.tick();
+            page
+        })
+        .boxed_local()
+}
+
+fn limit_total(
+    pages: LocalBoxStream<'_, Result<Vec<Conversation>, Box<dyn Error>>>,
+    limit: usize,
+) -> LocalBoxStream<'_, Result<Vec<Conversation>, Box<dyn Error>>> {
+    stream::unfold((pages, limit), move |(mut pages, remaining)| async move {
+        if remaining == 0 {
+            return None;
+        }
+        let page = match pages.next().await? {
+            Ok(mut page) => {
+                page.truncate(remaining);
+                page
+            }
+            Err(error) => return Some((Err(error), (pages, remaining))),
+        };
+        let remaining = remaining - page.len();
+        if remaining == 0 {
+            warn!(
+                "Stopped listing conversations at --limit-total {}, so the results are truncated",
+                limit
+            );
+        }
+        Some((Ok(page), (pages, remaining)))
+    })
+    .boxed_local()
+}
+
+/// Picks `amount` of `items` at random, keeping them in order.
+/// Every subset is equally likely, and the same `seed` always picks the same one.
+fn sample<T>(items: Vec<T>, amount: usize, seed: Option<u64>) -> Vec<T> {
+    let mut rng = match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => rand::make_rng(),
+    };
+    let mut picked = vec![false; items.len()];
+    for index in index::sample(&mut rng, items.len(), amount.min(items.len())) {
+        picked[index] = true;
+    }
+    items
+        .into_iter()
+        .zip(picked)
+        .filter_map(|(item, picked)| if picked { Some(item) } else { None })
+        .collect()
+}
+
+/// Prints just the ID, ending with a null character instead of a newline if `null` is set.
+fn print_id(
+    out: &mut dyn Write,
+    conversation: &NormalizedConversation,
+    null: bool,
+) -> io::Result<()> {
+    write!(out, "{}{}", conversation.id, if null { '\0' } else { '\n' })
+}
+
+/// Prints `conversation` as a line of JSON, flushed right away
+/// so whatever's reading it doesn't wait on a buffer.
+fn print_json_line(out: &mut dyn Write, conversation: &NormalizedConversation) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, conversation)?;
+    writeln!(out)?;
+    out.flush()
+}
+
+fn print_table(
+    out: &mut dyn Write,
+    conversations: &[NormalizedConversation],
+    fields: Option<&[&str]>,
+) -> io::Result<()> {
+    let fields = fields.unwrap_or(&["id", "type", "name"]);
+    let widths = fields
+        .iter()
+        .map(|field| {
+            conversations
+                .iter()
+                .map(|convo| convo.field(field).chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<usize>>();
+
+    for conversation in conversations {
+        let (icon, color) = conversation.icon_and_color();
+        let columns = fields
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(i, (field, width))| {
+                // Padding the last column would only add trailing whitespace
+                let value = if i + 1 == fields.len() {
+                    conversation.field(field)
+                } else {
+                    format!("{:<width$}", conversation.field(field), width = width)
+                };
+                if *field == "id" {
+                    value.bold().to_string()
+                } else {
+                    value
+                }
+            })
+            .collect::<Vec<String>>();
+        writeln!(
+            out,
+            "{}",
+            format!("{} {}", icon, columns.join(" ")).color(color)
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Saves `messages` from `channel` into the `messages` table, creating it if needed.
+/// Messages that were already saved are replaced, so exports can be repeated.
+fn save_messages(
+    connection: &mut Connection,
+    channel: &str,
+    messages: &[Message],
+) -> rusqlite::Result<()> {
+    let transaction = connection.transaction()?;
+    transaction.execute_batch(
+        "CREATE TABLE IF NOT EXISTS messages (
+            ts TEXT NOT NULL,
+            user TEXT,
+            channel TEXT NOT NULL,
+            text TEXT NOT NULL,
+            PRIMARY KEY (channel, ts)
+        );
+        CREATE INDEX IF NOT EXISTS messages_ts ON messages (ts);
+        CREATE INDEX IF NOT EXISTS messages_user ON messages (user);",
+    )?;
+    {
+        let mut insert = transaction.prepare(
+            "INSERT OR REPLACE INTO messages (ts, user, channel, text) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for message in messages {
+            insert.execute(params![message.ts, message.user, channel, message.text])?;
+        }
+    }
+    transaction.commit()
+}
+
+/// Prints how many conversations of each type were listed,
+/// using `counts` keyed by type name.
+fn print_footer(
+    out: &mut dyn Write,
+    counts: &HashMap<&str, u64>,
+    locale: Locale,
+) -> io::Result<()> {
+    writeln!(out)?;
+    for type_name in TYPES.iter() {
+        writeln!(
+            out,
+            "{}: {}",
+            type_name,
+            counts
+                .get(type_name)
+                .unwrap_or(&0)
+                .to_formatted_string(&locale)
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Prints `fields` of `conversations` as CSV, defaulting to `CSV_FIELDS`.
+fn print_csv(
+    out: &mut dyn Write,
+    conversations: &[NormalizedConversation],
+    fields: Option<&[&str]>,
+) -> io::Result<()> {
+    let fields = fields.unwrap_or(&CSV_FIELDS);
+    writeln!(out, "{}", fields.join(","))?;
+    for conversation in conversations {
+        let row = fields
+            .iter()
+            .map(|field| match *field {
+                // Spreadsheets don't need the type identifiers or annotations
+                "name" => csv_quote(&conversation.names.join(", ")),
+                field => csv_quote(&conversation.field(field)),
+            })
+            .collect::<Vec<String>>();
+        writeln!(out, "{}", row.join(","))?;
+    }
+    Ok(())
+}
+
+/// Quotes `value` if it contains commas, quotes, or line breaks, doubling any quotes.
+fn csv_quote(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Resolves `conversation` to its ID.
+/// IDs are returned as-is, `#name` matches public and private channels,
+/// `&name` matches conversations with multiple members by name, e.g. `&mpdm-alice--bob-1`,
+/// or by their members' names, e.g. `&alice,bob`,
+/// and `@name` matches DMs with that user.
+async fn resolve_conversation(
+    slack: &dyn SlackApi,
+    conversation: &str,
+) -> Result<String, Box<dyn Error>> {
+    let (types, name) = if let Some(name) = conversation.strip_prefix('#') {
+        (vec!["public_channel", "private_channel"], name)
+    } else if let Some(name) = conversation.strip_prefix('&') {
+        (vec!["mpim"], name)
+    } else if let Some(name) = conversation.strip_prefix('@') {
+        (vec!["im"], name)
+    } else {
+        return Ok(conversation.to_string());
+    };
+    let types = types.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+
+    debug!("Resolving {}...", conversation);
+    // DMs are named after the other user, so only users with that name are of interest
+    let mut user_ids = vec![];
+    if conversation.starts_with('@') {
+        for user in slack.list_users().await? {
+            let id = user.id().to_string();
+            if user.profile_field(ProfileField::Name) == name {
+                user_ids.push(id);
+            }
+        }
+    }
+    let mut member_names = name.split(',').collect::<Vec<&str>>();
+    member_names.sort_unstable();
+
+    let mut ids = vec![];
+    for convo in slack.list_conversations(&types, false, None, None).await? {
+        let matches = match &convo {
+            Conversation::PublicChannel(convo) => convo.name == name,
+            Conversation::PrivateChannel(convo) => convo.name == name,
+            Conversation::Mpim(convo) => {
+                let mut names = mpim_names(&convo.name);
+                names.sort_unstable();
+                convo.name == name || names == member_names
+            }
+            Conversation::Im(convo) => user_ids.contains(&convo.user),
+        };
+        if matches {
+            ids.push(convo.id().to_string());
+        }
+    }
+
+    match ids.len() {
+        0 => Err(format!(
+            "Couldn't find a conversation named `{}`",
+            conversation
+        ))?,
+        1 => Ok(ids.remove(0)),
+        _ => Err(format!(
+            "`{}` could be any of {}. Use an ID instead.",
+            conversation,
+            ids.join(", ")
+        ))?,
+    }
+}
+
+/// ID of the workspace's general channel, which every member belongs to and can't leave.
+async fn find_general(slack: &dyn SlackApi) -> Result<Option<String>, Box<dyn Error>> {
+    let types = vec!["public_channel".to_string()];
+    Ok(slack
+        .list_conversations(&types, false, None, None)
+        .await?
+        .into_iter()
+        .find_map(|conversation| match conversation {
+            Conversation::PublicChannel(convo) if convo.is_general => Some(convo.id),
+            _ => None,
+        }))
+}
+
+/// Warns that `conversation`, the general channel, is being left alone.
+fn warn_general(conversation: &str) {
+    warn!(
+        "Skipping {} since it's the general channel, which everyone in the workspace is in and relies on. Pass --include-general to delete from it anyway.",
+        conversation
+    );
+}
+
+async fn info(slack: &dyn SlackApi, options: &ArgMatches<'_>, locale: Locale) {
+    let conversation = &resolve_conversation(slack, options.value_of("CHANNEL").unwrap())
+        .await
+        .unwrap();
+
+    let progress = ProgressBar::hidden();
+    let mut conversation = normalize_conversation(
+        slack,
+        slack.get_conversation(conversation).await.unwrap(),
+        false,
+        ProfileField::Name,
+        &progress,
+    )
+    .await;
+    resolve_creator(slack, &mut conversation, ProfileField::Name, &progress).await;
+
+    print_info(&mut io::stdout(), &conversation, locale).unwrap();
+}
+
+fn print_info(
+    out: &mut dyn Write,
+    conversation: &NormalizedConversation,
+    locale: Locale,
+) -> io::Result<()> {
+    let none = || "-".to_string();
+    let lines = [
+        ("ID", conversation.id.clone()),
+        ("Name", conversation.display_names()),
+        ("Type", conversation.type_name().to_string()),
+        ("Created", format_date(conversation.created)),
+        (
+            "Status",
+            if conversation.is_archived {
+                "archived"
+            } else {
+                "active"
+            }
+            .to_string(),
+        ),
+        (
+            "Member",
+            if conversation.is_member { "yes" } else { "no" }.to_string(),
+        ),
+        (
+            "Members",
+            conversation
+                .num_members
+                .map_or_else(none, |num_members| num_members.to_formatted_string(&locale)),
+        ),
+        (
+            "Creator",
+            conversation.creator.as_ref().map_or_else(none, |creator| {
+                if conversation.is_creator_deleted {
+                    format!("@{} (deactivated)", creator)
+                } else {
+                    format!("@{}", creator)
+                }
+            }),
+        ),
+        (
+            "Topic",
+            conversation
+                .topic
+                .clone()
+                .filter(|text| !text.is_empty())
+                .unwrap_or_else(none),
+        ),
+        (
+            "Purpose",
+            conversation
+                .purpose
+                .clone()
+                .filter(|text| !text.is_empty())
+                .unwrap_or_else(none),
+        ),
+    ];
+    for (label, value) in &lines {
+        writeln!(out, "{:<8} {}", format!("{}:", label), value)?;
+    }
+    Ok(())
+}
+
+async fn archive(slack: &dyn SlackApi, options: &ArgMatches<'_>) {
+    let conversation = &resolve_conversation(slack, options.value_of("CHANNEL").unwrap())
+        .await
+        .unwrap();
+
+    match slack.archive_conversation(conversation).await.unwrap() {
+        Archival::Archived => info!("Archived {}", conversation),
+        Archival::AlreadyArchived => info!("{} is already archived", conversation),
+    }
+}
+
+async fn unarchive(slack: &dyn SlackApi, options: &ArgMatches<'_>) {
+    let conversation = &resolve_conversation(slack, options.value_of("CHANNEL").unwrap())
+        .await
+        .unwrap();
+
+    match slack.unarchive_conversation(conversation).await.unwrap() {
+        Unarchival::Unarchived => info!("Unarchived {}", conversation),
+        Unarchival::NotArchived => info!("{} isn't archived", conversation),
+    }
+}
+
+async fn export(slack: &dyn SlackApi, options: &ArgMatches<'_>, locale: Locale) {
+    let conversation = &resolve_conversation(slack, options.value_of("CHANNEL").unwrap())
+        .await
+        .unwrap();
+    let (oldest, latest) = time_window(options);
+    let since = if options.is_present("since_last_run") {
+        let dir = watermark::dir().expect("Couldn't find the config directory, set $HOME");
+        let since = watermark::read(&dir, conversation).unwrap();
+        if since.is_none() {
+            info!(
+                "{} wasn't exported before, exporting every message",
+                conversation
+            );
+        }
+        since
+    } else {
+        None
+    };
+    let oldest = since.as_deref().map(watermark::seconds).or(oldest);
+
+    info!("Retrieving messages from {}...", conversation);
+    let mut messages = slack
+        .get_history(
+            conversation,
+            oldest,
+            latest,
+            !options.is_present("no_threads"),
+        )
+        .await
+        .unwrap();
+    if let Some(since) = &since {
+        // `oldest` only has whole seconds, so it can include the newest message exported last time
+        messages.retain(|message| watermark::is_newer(&message.ts, since));
+    }
+    let newest =
+        watermark::newest(messages.iter().map(|message| message.ts.as_str())).map(str::to_string);
+
+    if options.is_present("resolve_mentions") {
+        let mut names = HashMap::new();
+        for message in &messages {
+            for user in mrkdwn::mentioned_users(&message.text) {
+                if names.contains_key(&user) {
+                    continue;
+                }
+                match slack.get_user_name(&user).await {
+                    Ok(name) => {
+                        names.insert(user, name);
+                    }
+                    Err(error) => debug!("Couldn't retrieve mentioned user {}: {}", user, error),
+                }
+            }
+        }
+        for message in &mut messages {
+            message.text = mrkdwn::resolve_mentions(&message.text, &names);
+        }
+    }
+    if options.is_present("strip_formatting") {
+        for message in &mut messages {
+            message.text = mrkdwn::strip_formatting(&message.text);
+        }
+    }
+
+    if let Some(path) = options.value_of("sqlite") {
+        let mut connection = Connection::open(path).unwrap();
+        save_messages(&mut connection, conversation, &messages).unwrap();
+        info!(
+            "Exported {} messages from {} to {}",
+            messages.len().to_formatted_string(&locale),
+            conversation,
+            path
+        );
+        record_newest(conversation, newest);
+        return;
+    }
+
+    let mut out: Box<dyn Write> = match options.value_of("output_file") {
+        Some(path) => Box::new(BufWriter::new(File::create(path).unwrap())),
+        None => Box::new(io::stdout()),
+    };
+    for message in &messages {
+        serde_json::to_writer(&mut out, message).unwrap();
+        writeln!(out).unwrap();
+    }
+    out.flush().unwrap();
+
+    info!(
+        "Exported {} messages from {}",
+        messages.len().to_formatted_string(&locale),
+        conversation
+    );
+    record_newest(conversation, newest);
+}
+
+/// Records `newest` as the newest message exported from `conversation` for `--since-last-run`.
+fn record_newest(conversation: &str, newest: Option<String>) {
+    let (dir, newest) = match (watermark::dir(), newest) {
+        (Some(dir), Some(newest)) => (dir, newest),
+        _ => return,
+    };
+    if let Err(error) = watermark::record(&dir, conversation, &newest) {
+        warn!(
+            "Couldn't record the newest message exported from {}: {}",
+            conversation, error
+        );
+    }
+}
+
+async fn files(
+    slack: &dyn SlackApi,
+    options: &ArgMatches<'_>,
+    filter: LevelFilter,
+    locale: Locale,
+) -> Result<(), Box<dyn Error>> {
+    match options.subcommand() {
+        ("list", Some(options)) => {
+            let conversation = match options.value_of("channel") {
+                Some(channel) => Some(resolve_conversation(slack, channel).await.unwrap()),
+                None => None,
+            };
+            let mut files = slack
+                .list_files(options.value_of("user"), conversation.as_deref())
+                .await
+                .unwrap();
+            files.sort_unstable_by_key(|file| file.created);
+
+            for file in files {
+                println!(
+                    "{}: {} ({} bytes, uploaded {} by {})",
+                    file.id.bold(),
+                    file.name.as_ref().unwrap_or(&file.title),
+                    file.size.to_formatted_string(&locale),
+                    format_date(file.created),
+                    file.user
+                );
+            }
+        }
+        ("delete", Some(options)) => {
+            let files = options.values_of("FILE").unwrap().collect::<Vec<&str>>();
+            if !confirm(
+                &format!("Delete {} files?", files.len().to_formatted_string(&locale)),
+                filter,
+            ) {
+                info!("No files were deleted");
+                return Ok(());
+            }
+
+            let progress = progress_bar(files.len() as u64, filter);
+
+            let mut deleted: u64 = 0;
+            let mut skipped: u64 = 0;
+            let mut failed = Ok(());
+            for file in files {
+                match slack.delete_file(file).await {
+                    Ok(Deletion::Deleted) => deleted += 1,
+                    Ok(Deletion::Skipped(reason)) => {
+                        debug!("Skipped file {}: {}", file, reason);
+                        skipped += 1;
+                    }
+                    Err(error) => {
+                        failed = Err(format!("Couldn't delete file {}: {}", file, error));
+                        break;
+                    }
+                }
+                progress.inc(1);
+            }
+            progress.finish_and_clear();
+
+            info!(
+                "Deleted {} files and skipped {} files that couldn't be deleted",
+                deleted.to_formatted_string(&locale),
+                skipped.to_formatted_string(&locale)
+            );
+            failed?;
+        }
+        (cmd, _) => panic!("Unsupported command: files {}", cmd),
+    }
+    Ok(())
+}
+
+async fn stats(slack: &dyn SlackApi, filter: LevelFilter, locale: Locale) {
+    let progress = progress_bar(0, filter);
+
+    let types = TYPES.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+    let conversations = report_pages(
+        slack.conversation_pages(&types, false, None, None),
+        &progress,
+    )
+    .try_concat()
+    .await
+    .unwrap();
+    let named = oldest_and_newest(slack, &conversations, &progress).await;
+    progress.finish_and_clear();
+
+    print_stats(&mut io::stdout(), &conversations, &named, locale).unwrap();
+}
+
+/// The oldest and newest of `conversations`, normalized so they can be named.
+/// Nothing else needs a name, so the users of every other DM aren't looked up.
+async fn oldest_and_newest(
+    slack: &dyn SlackApi,
+    conversations: &[Conversation],
+    progress: &ProgressBar,
+) -> Vec<(&'static str, NormalizedConversation)> {
+    let oldest = conversations.iter().min_by_key(|convo| convo.created());
+    let newest = conversations.iter().max_by_key(|convo| convo.created());
+    let mut named = vec![];
+    for (label, conversation) in &[("oldest", oldest), ("newest", newest)] {
+        if let Some(conversation) = conversation {
+            let conversation = (*conversation).clone();
+            named.push((
+                *label,
+                normalize_conversation(slack, conversation, false, ProfileField::Name, progress)
+                    .await,
+            ));
+        }
+    }
+    named
+}
+
+fn print_stats(
+    out: &mut dyn Write,
+    conversations: &[Conversation],
+    named: &[(&str, NormalizedConversation)],
+    locale: Locale,
+) -> io::Result<()> {
+    for type_name in TYPES.iter() {
+        let count = conversations
+            .iter()
+            .filter(|convo| convo.type_name() == *type_name)
+            .count();
+        writeln!(out, "{}: {}", type_name, count.to_formatted_string(&locale))?;
+    }
+
+    let archived = conversations
+        .iter()
+        .filter(|convo| convo.is_archived())
+        .count();
+    writeln!(out, "archived: {}", archived.to_formatted_string(&locale))?;
+
+    let members = conversations
+        .iter()
+        .filter_map(|convo| match convo {
+            Conversation::PublicChannel(convo) => Some(convo.num_members),
+            _ => None,
+        })
+        .sum::<u64>();
+    writeln!(
+        out,
+        "public_channel members: {}",
+        members.to_formatted_string(&locale)
+    )?;
+
+    for (label, conversation) in named {
+        writeln!(
+            out,
+            "{}: {} ({}), created {}",
+            label,
+            conversation.display_names(),
+            conversation.id,
+            format_date(conversation.created)
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Formats a Unix timestamp as a UTC date, e.g. `2019-11-29`.
+fn format_date(timestamp: u64) -> String {
+    Utc.timestamp_opt(timestamp as i64, 0).single().map_or_else(
+        || timestamp.to_string(),
+        |date| date.format("%Y-%m-%d").to_string(),
+    )
+}
+
+fn workspaces() {
+    let dir = workspaces_dir().expect("Couldn't find the config directory");
+    let workspaces = match list_workspaces(&dir) {
+        Ok(workspaces) => workspaces,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => vec![],
+        Err(error) => panic!("Couldn't list workspaces in {}: {}", dir.display(), error),
+    };
+    if workspaces.is_empty() {
+        info!(
+            "No workspaces found. Save a token to {} to add one.",
+            dir.join("<name>.token").display()
+        );
+        return;
+    }
+
+    for workspace in workspaces {
+        println!("{}", workspace);
+    }
+}
+
+async fn users(slack: &dyn SlackApi, options: Option<&ArgMatches<'_>>) {
+    let mut substring = "";
+    let mut json = false;
+    if let Some(options) = options {
+        if let Some(provided_substring) = options.value_of("SUBSTRING") {
+            substring = provided_substring;
+        }
+        if options.is_present("json") {
+            json = true;
+        }
+    }
+
+    let mut users = slack
+        .list_users()
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|user| match user {
+            User::Active(user) => NormalizedUser {
+                id: user.id,
+                name: user.name,
+                real_name: user.real_name,
+                is_deleted: false,
+            },
+            User::Deleted(user) => NormalizedUser {
+                id: user.id,
+                name: user.name,
+                real_name: user.profile.real_name,
+                is_deleted: true,
+            },
+        })
+        .filter(|user| user.name.contains(substring) || user.real_name.contains(substring))
+        .collect::<Vec<NormalizedUser>>();
+
+    users.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+    if json {
+        println!("{}", serde_json::to_string(&users).unwrap());
+        return;
+    }
+
+    for user in users {
+        let color = if user.is_deleted {
+            Color::Red
+        } else {
+            Color::White
+        };
+        println!(
+            "{}",
+            format!("{}: @{} ({})", user.id.bold(), user.name, user.real_name).color(color)
+        );
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct NormalizedUser {
+    id: String,
+    name: String,
+    real_name: String,
+    is_deleted: bool,
+}
+
+/// IDs of the users `author` refers to, which may be either a user ID or name.
+/// Users from other organizations aren't listed, so an unlisted ID is looked up on its own.
+async fn resolve_author(slack: &dyn SlackApi, author: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let author = author.trim_start_matches('@');
+    debug!("Resolving user {}...", author);
+    let mut ids = vec![];
+    for user in slack.list_users().await? {
+        let id = user.id().to_string();
+        if id == author || user.profile_field(ProfileField::Name) == author {
+            ids.push(id);
+        }
+    }
+    if !ids.is_empty() {
+        return Ok(ids);
+    }
+    match slack.get_user(author).await {
+        Ok(user) => Ok(vec![user.id().to_string()]),
+        Err(error) => {
+            debug!("Couldn't look up {} as a user ID: {}", author, error);
+            Err(format!("Couldn't find a user named `{}`", author))?
+        }
+    }
+}
+
+/// Keeps messages sent by any of `authors`, which are user IDs.
+fn filter_by_author(messages: Vec<Message>, authors: &[String]) -> Vec<Message> {
+    messages
+        .into_iter()
+        .filter(|message| match &message.user {
+            Some(user) => authors.contains(user),
+            None => false,
+        })
+        .collect()
+}
+
+/// Parses durations like `30d`, `12h`, or `2w` into seconds.
+fn parse_duration(duration: &str) -> Result<u64, String> {
+    let unit = duration
+        .chars()
+        .last()
+        .ok_or_else(|| "Duration can't be empty".to_string())?;
+    let seconds = match unit {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 60 * 60 * 24,
+        'w' => 60 * 60 * 24 * 7,
+        _ => {
+            return Err(format!(
+                "Unknown unit `{}` in `{}`. Use s, m, h, d, or w, e.g. 30d.",
+                unit, duration
+            ))
+        }
+    };
+    let amount = duration[..duration.len() - unit.len_utf8()]
+        .parse::<u64>()
+        .map_err(|_| {
+            format!(
+                "`{}` must be a whole number followed by a unit, e.g. 30d.",
+                duration
+            )
+        })?;
+
+    amount
+        .checked_mul(seconds)
+        .ok_or_else(|| format!("`{}` is too long", duration))
+}
+
+/// Parses dates like `2024-01-31` (midnight UTC), times like `2024-01-31T09:00:00Z`,
+/// or Unix timestamps into seconds since the epoch.
+fn parse_date(date: &str) -> Result<u64, String> {
+    let timestamp = if let Ok(timestamp) = date.parse::<u64>() {
+        return Ok(timestamp);
+    } else if let Ok(time) = DateTime::parse_from_rfc3339(date) {
+        time.timestamp()
+    } else if let Ok(day) = NaiveDate::parse_from_str(date, "%Y-%m-%d") {
+        day.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp()
+    } else {
+        return Err(format!(
+            "`{}` isn't a date, e.g. 2024-01-31, 2024-01-31T09:00:00Z, or 1706691600.",
+            date
+        ));
+    };
+    u64::try_from(timestamp).map_err(|_| format!("`{}` is before 1970", date))
+}
+
+/// Unix timestamps of `--after` and `--before`,
+/// falling back to `--newer-than` and `--older-than` relative to now.
+fn time_window(options: &ArgMatches<'_>) -> (Option<u64>, Option<u64>) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let at = |date, duration| {
+        options
+            .value_of(date)
+            .map(|date| parse_date(date).unwrap())
+            .or_else(|| {
+                options
+                    .value_of(duration)
+                    .map(|duration| now.saturating_sub(parse_duration(duration).unwrap()))
+            })
+    };
+    (at("after", "newer_than"), at("before", "older_than"))
+}
+
+/// Progress bar counting up to `length`, hidden when output is silenced.
+fn progress_bar(length: u64, filter: LevelFilter) -> ProgressBar {
+    let progress = ProgressBar::new(length);
+    progress.set_style(
+        ProgressStyle::default_bar()
+            .template("{elapsed_precise} [{bar:40}] {pos:>7}/{len:7}")
+            .progress_chars("=> "),
+    );
+    if filter == LevelFilter::Off {
+        progress.set_draw_target(ProgressDrawTarget::hidden());
+    }
+    progress
+}
+
+/// Deletes `messages` from `conversation`, counting how many were deleted, skipped, and failed in `tally`.
+/// Failures are logged and passed over unless `fail_fast` is set, in which case the first one is returned.
+/// Requests are paced by `pacer` to stay under Slack's limit for `chat.delete`.
+async fn delete_messages(
+    slack: &dyn SlackApi,
+    conversation: &str,
+    messages: Vec<Message>,
+    progress: &ProgressBar,
+    fail_fast: bool,
+    tally: &mut Tally,
+    pacer: &mut Pacer,
+) -> Result<(), Box<dyn Error>> {
+    for message in messages {
+        pacer.acquire().await;
+        let rate_limits = slack.rate_limits();
+        let deletion = slack.delete_message(conversation, &message.ts).await;
+        if slack.rate_limits() > rate_limits {
+            pacer.slow_down();
+        }
+        match deletion {
+            Ok(Deletion::Deleted) => tally.deleted += 1,
+            Ok(Deletion::Skipped(reason)) => {
+                debug!("Skipped message {}: {}", message.ts, reason);
+                tally.skipped += 1;
+            }
+            Err(error) if fail_fast => {
+                progress.abandon();
+                tally.failed += 1;
+                Err(format!(
+                    "Couldn't delete message {} from {} after deleting {} messages: {}",
+                    message.ts, conversation, tally.deleted, error
+                ))?;
+            }
+            Err(error) => {
+                warn!(
+                    "Couldn't delete message {} from {}: {}",
+                    message.ts, conversation, error
+                );
+                tally.failed += 1;
+            }
+        }
+        progress.inc(1);
+    }
+    Ok(())
+}
+
+/// How many messages were deleted, skipped because they can't be deleted,
+/// or failed to delete.
+#[derive(Debug, Default, PartialEq)]
+struct Tally {
+    deleted: u64,
+    skipped: u64,
+    failed: u64,
+}
+impl Tally {
+    fn summary(&self, locale: Locale) -> String {
+        let mut summary = format!(
+            "Deleted {} messages and skipped {} messages that couldn't be deleted",
+            self.deleted.to_formatted_string(&locale),
+            self.skipped.to_formatted_string(&locale)
+        );
+        if self.failed > 0 {
+            summary.push_str(&format!(
+                ", {} messages failed to delete",
+                self.failed.to_formatted_string(&locale)
+            ));
+        }
+        summary
+    }
+
+    /// Adds the counts in `other`, e.g. from another conversation.
+    fn add(&mut self, other: &Tally) {
+        self.deleted += other.deleted;
+        self.skipped += other.skipped;
+        self.failed += other.failed;
+    }
+
+    /// How quickly messages were deleted over `elapsed`.
+    fn rate(&self, elapsed: Duration) -> String {
+        let attempted = self.deleted + self.skipped + self.failed;
+        let per_minute = attempted as f64 * 60.0 / elapsed.as_secs_f64().max(1.0);
+        format!("Averaged {:.1} deletions per minute", per_minute)
+    }
+}
+
+/// `chat.delete` is a tier 3 method, allowing about 50 requests a minute with short bursts.
+fn deletion_pacer() -> Pacer {
+    Pacer::new(48, 5)
+}
+
+/// Asks the user a yes/no `question`, defaulting to no.
+/// Nothing is printed with --silent, so the answer is yes without asking.
+fn confirm(question: &str, filter: LevelFilter) -> bool {
+    if filter == LevelFilter::Off {
+        return true;
+    }
+    let answer = prompt(&format!("{} [y/N]", question));
+    matches!(answer.to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Asks the user `question`, returning their trimmed answer.
+fn prompt(question: &str) -> String {
+    print!("{} ", question);
+    io::stdout().flush().unwrap();
+
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer).unwrap();
+    answer.trim().to_string()
+}
+
+/// Zero-based indices of the items picked in `selection`, e.g. `1, 3-5`,
+/// out of `count` items numbered from one.
+fn parse_selection(selection: &str, count: usize) -> Result<Vec<usize>, String> {
+    let number = |value: &str| match value.trim().parse::<usize>() {
+        Ok(number) if (1..=count).contains(&number) => Ok(number - 1),
+        _ => Err(format!(
+            "`{}` isn't a number between 1 and {}",
+            value.trim(),
+            count
+        )),
+    };
+    let mut picked = vec![];
+    for part in selection.split(',').filter(|part| !part.trim().is_empty()) {
+        let (first, last) = match part.split_once('-') {
+            Some((first, last)) => (number(first)?, number(last)?),
+            None => (number(part)?, number(part)?),
+        };
+        if first > last {
+            return Err(format!("`{}` should go from low to high", part.trim()));
+        }
+        picked.extend(first..=last);
+    }
+    picked.sort_unstable();
+    picked.dedup();
+    Ok(picked)
+}
+
+/// Deletes messages from the conversations in `options`, one after another,
+/// returning how many were deleted, skipped, and failed across all of them.
+async fn rm(
+    slack: &dyn SlackApi,
+    options: &ArgMatches<'_>,
+    filter: LevelFilter,
+    locale: Locale,
+    concurrency: usize,
+) -> Result<Tally, Box<dyn Error>> {
+    let targets = if let Some(channels) = options.values_of("CHANNEL") {
+        // Finding the general channel means listing every public channel, so it's skipped when allowed anyway
+        let general = if options.is_present("include_general") {
+            None
+        } else {
+            find_general(slack).await.unwrap()
+        };
+        let mut targets = vec![];
+        for channel in channels {
+            let conversation = resolve_conversation(slack, channel).await.unwrap();
+            let is_general = general.as_deref() == Some(&conversation);
+            targets.push((conversation, is_general));
+        }
+        targets
+    } else {
+        match pick_conversations(slack, filter, concurrency).await {
+            Some(targets) => targets,
+            None => return Ok(Tally::default()),
+        }
+    };
+
+    let scopes = slack.test_auth().await.unwrap().scopes;
+    let authors = match options.value_of("from") {
+        Some(author) => Some(resolve_author(slack, author).await?),
+        None => None,
+    };
+    let mut pacer = deletion_pacer();
+    let mut total = Tally::default();
+    // Conversations messages were deleted from, or would be with --dry-run
+    let mut processed = 0;
+    let mut would_delete = 0;
+    let mut deleted = Ok(());
+    for (conversation, is_general) in &targets {
+        if *is_general && !options.is_present("include_general") {
+            warn_general(conversation);
+            continue;
+        }
+        let (is_ext_shared, messages) = match deletable_messages(
+            slack,
+            conversation,
+            scopes.as_deref(),
+            authors.as_deref(),
+            options,
+        )
+        .await
+        {
+            Some(found) => found,
+            None => continue,
+        };
+
+        if options.is_present("count_only") {
+            if targets.len() == 1 {
+                println!("{}", messages.len());
+            } else {
+                println!("{}\t{}", conversation, messages.len());
+            }
+            continue;
+        }
+        if messages.is_empty() {
+            info!("No messages to delete in {}", conversation);
+            continue;
+        }
+        processed += 1;
+        if options.is_present("dry_run") {
+            info!(
+                "Would delete {} messages from {}",
+                messages.len().to_formatted_string(&locale),
+                conversation
+            );
+            would_delete += messages.len();
+            continue;
+        }
+        if is_ext_shared {
+            warn_ext_shared(conversation);
+        }
+        let (tally, result) = rm_messages(
+            slack,
+            conversation,
+            messages,
+            options,
+            &mut pacer,
+            filter,
+            locale,
+        )
+        .await;
+        total.add(&tally);
+        deleted = result;
+        if deleted.is_err() {
+            break;
+        }
+    }
+
+    if targets.len() > 1 && options.is_present("dry_run") {
+        info!(
+            "Would delete {} messages from {} conversations",
+            would_delete.to_formatted_string(&locale),
+            processed.to_formatted_string(&locale)
+        );
+    } else if targets.len() > 1 && !options.is_present("count_only") {
+        info!(
+            "Across {} conversations: {}",
+            processed.to_formatted_string(&locale),
+            total.summary(locale)
+        );
+    }
+    deleted.map(|_| total)
+}
+
+/// Whether to stop at the first message that fails to delete instead of `--continue`, the default.
+fn fail_fast(options: &ArgMatches<'_>) -> bool {
+    options.is_present("fail_fast") && !options.is_present("continue")
+}
+
+/// Lists conversations the user is a member of and asks which ones to delete messages from,
+/// returning their IDs and whether each is the general channel, or `None` if none were picked.
+async fn pick_conversations(
+    slack: &dyn SlackApi,
+    filter: LevelFilter,
+    concurrency: usize,
+) -> Option<Vec<(String, bool)>> {
+    let mut conversations = member_conversations(slack, filter, concurrency).await;
+    if conversations.is_empty() {
+        info!("No conversations to delete messages from");
+        return None;
+    }
+    for conversation in &mut conversations {
+        conversation.names.sort_unstable();
+    }
+    conversations.sort_unstable();
+    let width = conversations.len().to_string().len();
+    for (number, conversation) in conversations.iter().enumerate() {
+        println!(
+            "{:>width$}. {}: {}{}",
+            number + 1,
+            conversation.id.bold(),
+            conversation.display_names(),
+            conversation.annotations(),
+            width = width
+        );
+    }
+
+    let picked = loop {
+        let selection = prompt("Conversations to delete messages from, e.g. 1, 3-5:");
+        match parse_selection(&selection, conversations.len()) {
+            Ok(picked) => break picked,
+            Err(error) => warn!("{}", error),
+        }
+    };
+    if picked.is_empty() {
+        info!("No conversations were picked");
+        return None;
+    }
+    Some(
+        picked
+            .into_iter()
+            .map(|index| {
+                let conversation = &conversations[index];
+                (conversation.id.clone(), conversation.is_general)
+            })
+            .collect(),
+    )
+}
+
+/// Whether `conversation` is shared with other organizations and its messages matching `options`
+/// and sent by `authors` if given, or `None` if the token is missing `scopes` needed to delete from it.
+async fn deletable_messages(
+    slack: &dyn SlackApi,
+    conversation: &str,
+    scopes: Option<&[String]>,
+    authors: Option<&[String]>,
+    options: &ArgMatches<'_>,
+) -> Option<(bool, Vec<Message>)> {
+    // Details only decide what to warn about, so deleting goes ahead without them
+    let is_ext_shared = match slack.get_conversation(conversation).await {
+        Ok(details) => {
+            if let Some(scopes) = scopes {
+                let missing = missing_deletion_scopes(scopes, details.type_name());
+                if !missing.is_empty() {
+                    warn_missing_scopes(conversation, &missing);
+                    return None;
+                }
+            }
+            details.is_ext_shared()
+        }
+        Err(error) => {
+            warn!(
+                "Couldn't retrieve details of {}, so it isn't checked for missing scopes or sharing with other organizations: {}",
+                conversation, error
+            );
+            false
+        }
+    };
+
+    let (oldest, latest) = time_window(options);
+
+    info!("Retrieving messages from {}...", conversation);
+    let messages = slack
+        .get_history(
+            conversation,
+            oldest,
+            latest,
+            !options.is_present("no_threads"),
+        )
+        .await
+        .unwrap();
+    let messages = match authors {
+        Some(authors) => filter_by_author(messages, authors),
+        None => messages,
+    };
+    Some((is_ext_shared, messages))
+}
+
+/// Deletes `messages` from `conversation` once the user confirms,
+/// logging and returning how many were deleted, skipped, and failed.
+/// With `--fail-fast` in `options`, the first failure is returned alongside the counts so far.
+async fn rm_messages(
+    slack: &dyn SlackApi,
+    conversation: &str,
+    messages: Vec<Message>,
+    options: &ArgMatches<'_>,
+    pacer: &mut Pacer,
+    filter: LevelFilter,
+    locale: Locale,
+) -> (Tally, Result<(), Box<dyn Error>>) {
+    let mut tally = Tally::default();
+    if !confirm(
+        &format!(
+            "Delete {} messages from {}?",
+            messages.len().to_formatted_string(&locale),
+            conversation
+        ),
+        filter,
+    ) {
+        info!("No messages were deleted");
+        return (tally, Ok(()));
+    }
+
+    let progress = progress_bar(messages.len() as u64, filter);
+    let deleting = Instant::now();
+    let deleted = delete_messages(
+        slack,
+        conversation,
+        messages,
+        &progress,
+        fail_fast(options),
+        &mut tally,
+        pacer,
+    )
+    .await;
+    progress.finish_and_clear();
+
+    info!("{}", tally.summary(locale));
+    if deleted.is_ok() {
+        info!("{}", tally.rate(deleting.elapsed()));
+    }
+    (tally, deleted)
+}
+
+async fn edit(slack: &dyn SlackApi, options: &ArgMatches<'_>) -> Result<(), Box<dyn Error>> {
+    let conversation = &resolve_conversation(slack, options.value_of("CHANNEL").unwrap())
+        .await
+        .unwrap();
+    let ts = options.value_of("TS").unwrap();
+    let text = match options.value_of("text") {
+        Some(text) => text.to_string(),
+        None => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text).unwrap();
+            text.trim_end_matches(['\r', '\n']).to_string()
+        }
+    };
+    if text.is_empty() {
+        return Err(
+            "Slack doesn't allow empty messages, so provide some text to replace it with".into(),
+        );
+    }
+
+    match slack.update_message(conversation, ts, &text).await.unwrap() {
+        Edit::Edited => info!("Edited message {} in {}", ts, conversation),
+        Edit::Skipped(reason) => warn!(
+            "Couldn't edit message {} in {}: {}",
+            ts, conversation, reason
+        ),
+    }
+    Ok(())
+}
+
+/// Unarchived conversations the user is a member of, excluding DMs with deactivated users.
+async fn member_conversations(
+    slack: &dyn SlackApi,
+    filter: LevelFilter,
+    concurrency: usize,
+) -> Vec<NormalizedConversation> {
+    let progress = progress_bar(0, filter);
+    let types = TYPES.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+    let raw_conversations = report_pages(
+        slack.conversation_pages(&types, true, None, None),
+        &progress,
+    )
+    .try_concat()
+    .await
+    .unwrap();
+    progress.set_length(raw_conversations.len() as u64);
+
+    let progress = &progress;
+    let conversations = stream::iter(raw_conversations)
+        .map(|conversation| async move {
+            let conversation =
+                normalize_conversation(slack, conversation, false, ProfileField::Name, progress)
+                    .await;
+            progress.inc(1);
+            conversation
+        })
+        .buffered(concurrency)
+        .filter(|conversation| future::ready(conversation.is_member && !conversation.is_deleted))
+        .collect::<Vec<NormalizedConversation>>()
+        .await;
+    progress.finish_and_clear();
+    conversations
+}
+
+async fn purge(
+    slack: &dyn SlackApi,
+    options: &ArgMatches<'_>,
+    filter: LevelFilter,
+    locale: Locale,
+    concurrency: usize,
+) -> Result<(), Box<dyn Error>> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    let latest =
+        now.saturating_sub(parse_duration(options.value_of("older_than").unwrap()).unwrap());
+
+    let count_only = options.is_present("count_only");
+    let conversations = member_conversations(slack, filter, concurrency).await;
+    let scopes = slack.test_auth().await.unwrap().scopes;
+
+    // History is retrieved one conversation at a time to stay within rate limits
+    let mut purges = vec![];
+    for conversation in conversations {
+        if conversation.is_general && !options.is_present("include_general") {
+            warn_general(&conversation.display_names());
+            continue;
+        }
+        if let Some(scopes) = &scopes {
+            let missing = missing_deletion_scopes(scopes, conversation.type_name());
+            if !missing.is_empty() {
+                warn_missing_scopes(&conversation.display_names(), &missing);
+                continue;
+            }
+        }
+        debug!("Retrieving messages from {}...", conversation.id);
+        let messages = slack
+            .get_history(
+                &conversation.id,
+                None,
+                Some(latest),
+                !options.is_present("no_threads"),
+            )
+            .await
+            .unwrap();
+        if !messages.is_empty() {
+            if !count_only {
+                info!(
+                    "{}: {} messages",
+                    conversation.display_names(),
+                    messages.len().to_formatted_string(&locale)
+                );
+            }
+            if conversation.is_ext_shared {
+                warn_ext_shared(&conversation.display_names());
+            }
+            purges.push((conversation, messages));
+        }
+    }
+
+    let total = purges
+        .iter()
+        .map(|(_, messages)| messages.len() as u64)
+        .sum::<u64>();
+    if count_only {
+        println!("{}", total);
+        return Ok(());
+    }
+    if total == 0 {
+        info!("No messages to delete");
+        return Ok(());
+    }
+
+    let summary = format!(
+        "{} messages from {} conversations",
+        total.to_formatted_string(&locale),
+        purges.len().to_formatted_string(&locale)
+    );
+    if options.is_present("dry_run") {
+        info!("Would delete {}", summary);
+        return Ok(());
+    }
+    if !confirm(&format!("Delete {}?", summary), filter) {
+        info!("No messages were deleted");
+        return Ok(());
+    }
+
+    let progress = progress_bar(total, filter);
+    let mut tally = Tally::default();
+    let mut pacer = deletion_pacer();
+    let deleting = Instant::now();
+    let mut deleted = Ok(());
+    for (conversation, messages) in purges {
+        deleted = delete_messages(
+            slack,
+            &conversation.id,
+            messages,
+            &progress,
+            fail_fast(options),
+            &mut tally,
+            &mut pacer,
+        )
+        .await;
+        if deleted.is_err() {
+            break;
+        }
+    }
+    progress.finish_and_clear();
+
+    info!("{}", tally.summary(locale));
+    deleted?;
+    info!("{}", tally.rate(deleting.elapsed()));
+    Ok(())
+}
+
+/// Warns that `name` is being left alone since the token lacks the `missing` scopes to clean it.
+fn warn_missing_scopes(name: &str, missing: &[&str]) {
+    warn!(
+        "Skipping {} since the token is missing the {} {} needed to delete messages from it",
+        name,
+        missing.join(" and "),
+        if missing.len() == 1 {
+            "scope"
+        } else {
+            "scopes"
+        }
+    );
+}
+
+/// Warns that deleting messages from `name` also deletes them for other organizations.
+fn warn_ext_shared(name: &str) {
+    warn!(
+        "{} is shared with other organizations, so deleted messages will be gone for their members too",
+        name
+    );
+}
+
+async fn read(
+    slack: &dyn SlackApi,
+    options: &ArgMatches<'_>,
+    filter: LevelFilter,
+    locale: Locale,
+    concurrency: usize,
+) {
+    let conversations = if options.is_present("all") {
+        member_conversations(slack, filter, concurrency)
+            .await
+            .into_iter()
+            .map(|conversation| (conversation.id, conversation.last_read))
+            .collect()
+    } else {
+        let conversation = resolve_conversation(slack, options.value_of("CHANNEL").unwrap())
+            .await
+            .unwrap();
+        vec![(conversation, None)]
+    };
+
+    let progress = progress_bar(conversations.len() as u64, filter);
+    let mut marked: u64 = 0;
+    for (conversation, last_read) in conversations {
+        progress.inc(1);
+        let latest = match slack.get_latest_message(&conversation).await.unwrap() {
+            Some(latest) => latest,
+            None => continue,
+        };
+        if last_read.is_some_and(|last_read| !is_unread(&last_read, Some(&latest))) {
+            continue;
+        }
+        debug!("Marking {} as read up to {}", conversation, latest.ts);
+        slack.mark_read(&conversation, &latest.ts).await.unwrap();
+        marked += 1;
+    }
+    progress.finish_and_clear();
+
+    info!(
+        "Marked {} conversations as read",
+        marked.to_formatted_string(&locale)
+    );
+}
+
+#[cfg(test)]
+// Some CLI tests pass their arguments as borrowed slices
+#[allow(clippy::needless_borrows_for_generic_args)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use slack::tests::{im_json, private_channel_json, public_channel_json, user_json};
+    use slack::{Auth, UploadedFile};
+    use std::cell::{Cell, RefCell};
+    use std::fs;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Serves canned conversations and users instead of calling Slack.
+    struct MockSlack {
+        conversations: Vec<String>,
+        users: HashMap<&'static str, String>,
+        /// Timestamps of the messages in each conversation
+        messages: HashMap<&'static str, Vec<&'static str>>,
+        /// Conversation and timestamp of each deleted message, in order
+        deleted: RefCell<Vec<(String, String)>>,
+    }
+
+    #[async_trait(?Send)]
+    impl SlackApi for MockSlack {
+        fn conversation_pages<'a>(
+            &'a self,
+            types: &[String],
+            _exclude_archived: bool,
+            _team_id: Option<&'a str>,
+            _start_cursor: Option<&str>,
+        ) -> LocalBoxStream<'a, Result<Vec<Conversation>, Box<dyn Error>>> {
+            let page = self
+                .conversations
+                .iter()
+                .map(|json| serde_json::from_str::<Conversation>(json).unwrap())
+                .filter(|conversation| types.iter().any(|t| t == conversation.type_name()))
+                .collect();
+            stream::once(future::ready(Ok(page))).boxed_local()
+        }
+
+        async fn test_auth(&self) -> Result<Auth, Box<dyn Error>> {
+            Ok(serde_json::from_str(
+                r#"{"ok": true, "url": "https://example.slack.com/", "team": "Example", "user": "alice", "team_id": "T0123456789", "user_id": "U0000000001"}"#,
+            )?)
+        }
+
+        async fn get_conversation(
+            &self,
+            conversation: &str,
+        ) -> Result<Conversation, Box<dyn Error>> {
+            self.conversations
+                .iter()
+                .map(|json| serde_json::from_str::<Conversation>(json).unwrap())
+                .find(|convo| convo.id() == conversation)
+                .ok_or_else(|| "channel_not_found".into())
+        }
+
+        async fn list_members(&self, _conversation: &str) -> Result<Vec<String>, Box<dyn Error>> {
+            Ok(self.users.keys().map(|id| id.to_string()).collect())
+        }
+
+        async fn list_users(&self) -> Result<Vec<User>, Box<dyn Error>> {
+            let mut users = vec![];
+            for user in self.users.values() {
+                users.push(serde_json::from_str(user)?);
+            }
+            Ok(users)
+        }
+
+        async fn get_user(&self, user: &str) -> Result<User, Box<dyn Error>> {
+            Ok(serde_json::from_str(
+                self.users.get(user).ok_or("user_not_found")?,
+            )?)
+        }
+
+        async fn get_history(
+            &self,
+            conversation: &str,
+            _oldest: Option<u64>,
+            _latest: Option<u64>,
+            _include_threads: bool,
+        ) -> Result<Vec<Message>, Box<dyn Error>> {
+            Ok(self
+                .messages
+                .get(conversation)
+                .into_iter()
+                .flatten()
+                .map(|ts| message(Some("U0000000001"), ts))
+                .collect())
+        }
+
+        async fn get_latest_message(
+            &self,
+            conversation: &str,
+        ) -> Result<Option<Message>, Box<dyn Error>> {
+            match conversation {
+                "C0000000002" => Err("ratelimited".into()),
+                _ => Ok(Some(message(Some("U0000000001"), "1575000001.000000"))),
+            }
+        }
+
+        async fn delete_message(
+            &self,
+            conversation: &str,
+            ts: &str,
+        ) -> Result<Deletion, Box<dyn Error>> {
+            match ts {
+                "failing" => Err("cant_delete_message")?,
+                _ => {
+                    self.deleted
+                        .borrow_mut()
+                        .push((conversation.to_string(), ts.to_string()));
+                    Ok(Deletion::Deleted)
+                }
+            }
+        }
+
+        async fn update_message(
+            &self,
+            _conversation: &str,
+            _ts: &str,
+            _text: &str,
+        ) -> Result<Edit, Box<dyn Error>> {
+            Ok(Edit::Edited)
+        }
+
+        async fn archive_conversation(
+            &self,
+            _conversation: &str,
+        ) -> Result<Archival, Box<dyn Error>> {
+            Ok(Archival::Archived)
+        }
+
+        async fn mark_read(&self, _conversation: &str, _ts: &str) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+
+        async fn unarchive_conversation(
+            &self,
+            _conversation: &str,
+        ) -> Result<Unarchival, Box<dyn Error>> {
+            Ok(Unarchival::Unarchived)
+        }
+
+        async fn list_files(
+            &self,
+            _user: Option<&str>,
+            _conversation: Option<&str>,
+        ) -> Result<Vec<UploadedFile>, Box<dyn Error>> {
+            Ok(vec![])
+        }
+
+        async fn delete_file(&self, file: &str) -> Result<Deletion, Box<dyn Error>> {
+            match file {
+                "F_MISSING" => Err("file_not_found".into()),
+                _ => Ok(Deletion::Deleted),
+            }
+        }
+    }
+
+    fn mock_slack() -> MockSlack {
+        let mut users = HashMap::new();
+        users.insert("U0000000001", user_json("U0000000001", "alice", false));
+        users.insert("U0000000002", user_json("U0000000002", "bob", false));
+        users.insert("U0000000003", user_json("U0000000003", "carol", true));
+
+        MockSlack {
+            conversations: vec![
+                im_json("D0000000002", "U0000000002"),
+                public_channel_json("C0000000002", "random"),
+                private_channel_json("G0000000001", "mpdm-carol--alice-1", true),
+                im_json("D0000000001", "U0000000001"),
+                private_channel_json("G0000000002", "secret-plans", false),
+                public_channel_json("C0000000001", "general")
+                    .replace(r#""is_general": false"#, r#""is_general": true"#),
+            ],
+            users,
+            messages: HashMap::new(),
+            deleted: RefCell::new(vec![]),
+        }
+    }
+
+    /// `output` without the escape codes `colored` adds when colors are enabled,
+    /// so tests don't depend on the terminal they run in.
+    fn uncolored(output: Vec<u8>) -> String {
+        let output = String::from_utf8(output).unwrap();
+        let mut uncolored = String::new();
+        let mut chars = output.chars();
+        while let Some(c) = chars.next() {
+            if c == '\u{1b}' {
+                chars.by_ref().find(|&c| c == 'm');
+            } else {
+                uncolored.push(c);
+            }
+        }
+        uncolored
+    }
+
+    /// What `tidy_slack ls` with `args` writes to its output file, listing from `slack`.
+    /// Tests start with time paused so listing doesn't wait out its pause after retrieving.
+    async fn ls_output(slack: &dyn SlackApi, args: &[&str]) -> String {
+        static RUNS: AtomicUsize = AtomicUsize::new(0);
+        let path = env::temp_dir().join(format!(
+            "tidy_slack-ls-{}-{}",
+            process::id(),
+            RUNS.fetch_add(1, Ordering::SeqCst)
+        ));
+        let options = build_cli().get_matches_from(
+            [
+                &["tidy_slack", "ls", "--output-file", path.to_str().unwrap()],
+                args,
+            ]
+            .concat(),
+        );
+        ls(
+            slack,
+            TYPES,
+            options.subcommand_matches("ls"),
+            LevelFilter::Off,
+            Locale::en,
+            1,
+        )
+        .await;
+        let output = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        output
+    }
+
+    async fn normalized(slack: &dyn SlackApi) -> Vec<NormalizedConversation> {
+        let mut conversations = vec![];
+        let types = TYPES.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        for conversation in slack
+            .list_conversations(&types, false, None, None)
+            .await
+            .unwrap()
+        {
+            conversations.push(
+                normalize_conversation(
+                    slack,
+                    conversation,
+                    false,
+                    ProfileField::Name,
+                    &ProgressBar::hidden(),
+                )
+                .await,
+            );
+        }
+        conversations
+    }
+
+    #[tokio::test]
+    async fn normalizes_each_conversation_type() {
+        let conversations = normalized(&mock_slack()).await;
+
+        let im = conversations
+            .iter()
+            .find(|convo| convo.id == "D0000000001")
+            .unwrap();
+        assert_eq!(im.type_identifier, "@");
+        assert_eq!(im.names, vec!["alice"]);
+        assert_eq!(im.member_ids, vec!["U0000000001"]);
+
+        let mpim = conversations
+            .iter()
+            .find(|convo| convo.id == "G0000000001")
+            .unwrap();
+        assert_eq!(mpim.type_identifier, "&");
+        assert_eq!(mpim.names, vec!["carol", "alice"]);
+
+        let private_channel = conversations
+            .iter()
+            .find(|convo| convo.id == "G0000000002")
+            .unwrap();
+        assert_eq!(private_channel.type_identifier, "!");
+    }
+
+    #[tokio::test]
+    async fn filters_by_substring() {
+        let conversations = normalized(&mock_slack())
+            .await
+            .into_iter()
+            .filter(|convo| convo.contains("al"))
+            .map(|convo| convo.id)
+            .collect::<Vec<String>>();
+
+        assert_eq!(
+            conversations,
+            vec!["G0000000001", "D0000000001", "C0000000001"]
+        );
+    }
+
+    #[test]
+    fn mpim_names_are_split_into_members() {
+        assert_eq!(mpim_names("mpdm-alice--bob-1"), vec!["alice", "bob"]);
+        assert_eq!(
+            mpim_names("mpdm-alice--bob--carol-1"),
+            vec!["alice", "bob", "carol"]
+        );
+        assert_eq!(mpim_names("mpdm-jean-luc--bob-12"), vec!["jean-luc", "bob"]);
+    }
+
+    #[test]
+    fn malformed_mpim_names_are_kept_whole() {
+        for name in &[
+            "mpdm",
+            "mpdm-",
+            "mpdm-alice--bob",
+            "mpdm-alice----bob-1",
+            "general",
+        ] {
+            assert_eq!(mpim_names(name), vec![name.to_string()]);
+        }
+    }
+
+    #[tokio::test]
+    async fn filters_by_normalized_name() {
+        let mut slack = mock_slack();
+        slack
+            .conversations
+            .push(public_channel_json("C0000000003", "josé").replace(
+                r#""name_normalized": "josé""#,
+                r#""name_normalized": "jose""#,
+            ));
+        let conversations = normalized(&slack).await;
+        let jose = conversations
+            .iter()
+            .find(|convo| convo.id == "C0000000003")
+            .unwrap();
+
+        assert!(!jose.contains("jose"));
+        assert!(jose.contains_normalized("jose"));
+    }
+
+    #[tokio::test]
+    async fn marks_shared_channels_when_asked() {
+        let mut slack = mock_slack();
+        slack.conversations.push(
+            public_channel_json("C0000000003", "partners")
+                .replace(r#""is_shared": false"#, r#""is_shared": true"#)
+                .replace(r#""is_ext_shared": false"#, r#""is_ext_shared": true"#),
+        );
+        let mut conversations = normalized(&slack).await;
+        let partners = conversations
+            .iter_mut()
+            .find(|convo| convo.id == "C0000000003")
+            .unwrap();
+
+        assert!(partners.is_ext_shared);
+        assert_eq!(partners.annotations(), "");
+        partners.show_shared = true;
+        assert_eq!(partners.annotations(), " (shared externally)");
+        assert!(slack
+            .get_conversation("C0000000003")
+            .await
+            .unwrap()
+            .is_ext_shared());
+    }
+
+    #[test]
+    fn samples_repeatably_in_order() {
+        let items = (0..100).collect::<Vec<u32>>();
+        let sampled = sample(items.clone(), 10, Some(42));
+
+        assert_eq!(sampled.len(), 10);
+        assert!(sampled.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(sample(items.clone(), 10, Some(42)), sampled);
+        assert_eq!(sample(items.clone(), 1000, None), items);
+        assert!(sample(items, 0, None).is_empty());
+    }
+
+    #[tokio::test]
+    async fn limits_total_without_retrieving_extra_pages() {
+        let page = || {
+            vec![
+                serde_json::from_str::<Conversation>(&public_channel_json(
+                    "C0000000001",
+                    "general",
+                ))
+                .unwrap(),
+                serde_json::from_str(&public_channel_json("C0000000002", "random")).unwrap(),
+            ]
+        };
+        let retrieved = Cell::new(0);
+        let pages = stream::iter(vec![page(), page(), page()])
+            .inspect(|_| retrieved.set(retrieved.get() + 1))
+            .map(Ok)
+            .boxed_local();
+
+        let limited = limit_total(pages, 3)
+            .map(|page| page.unwrap().len())
+            .collect::<Vec<usize>>()
+            .await;
+        assert_eq!(limited, [2, 1]);
+        assert_eq!(retrieved.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn resolves_parents_of_child_channels() {
+        let mut slack = mock_slack();
+        slack.conversations.push(
+            public_channel_json("C0000000003", "general-partners").replace(
+                r#""parent_conversation": null"#,
+                r#""parent_conversation": "C0000000001""#,
+            ),
+        );
+        slack
+            .conversations
+            .push(public_channel_json("C0000000004", "orphan").replace(
+                r#""parent_conversation": null"#,
+                r#""parent_conversation": "C0000000009""#,
+            ));
+        let mut conversations = normalized(&slack).await;
+        for conversation in &mut conversations {
+            resolve_parent(&slack, conversation, &ProgressBar::hidden()).await;
+        }
+        let parent = |id| {
+            conversations
+                .iter()
+                .find(|convo| convo.id == id)
+                .unwrap()
+                .annotations()
+        };
+
+        assert_eq!(parent("C0000000003"), " (in #general)");
+        assert_eq!(parent("C0000000004"), " (in C0000000009)");
+        assert_eq!(parent("C0000000002"), "");
+    }
+
+    #[tokio::test]
+    async fn marks_dms_with_bots() {
+        let mut slack = mock_slack();
+        slack
+            .users
+            .insert("USLACKBOT", user_json("USLACKBOT", "slackbot", false));
+        slack
+            .conversations
+            .push(im_json("D0000000003", "USLACKBOT"));
+        let bots = normalized(&slack)
+            .await
+            .into_iter()
+            .filter(|convo| convo.is_bot)
+            .map(|convo| convo.id)
+            .collect::<Vec<String>>();
+        assert_eq!(bots, vec!["D0000000003"]);
+    }
+
+    #[tokio::test]
+    async fn marks_dms_with_deleted_users() {
+        let mut slack = mock_slack();
+        slack.conversations.push(
+            im_json("D0000000003", "U0000000003")
+                .replace(r#""is_user_deleted": false"#, r#""is_user_deleted": true"#),
+        );
+        let deleted = normalized(&slack)
+            .await
+            .into_iter()
+            .filter(|convo| convo.is_deleted)
+            .map(|convo| convo.id)
+            .collect::<Vec<String>>();
+        assert_eq!(deleted, vec!["D0000000003"]);
+    }
+
+    #[tokio::test]
+    async fn sorts_by_type_then_name() {
+        let mut conversations = normalized(&mock_slack()).await;
+        for conversation in &mut conversations {
+            conversation.names.sort_unstable();
+        }
+        conversations.sort_unstable();
+
+        let listed = conversations
+            .iter()
+            .map(|convo| convo.display_names())
+            .collect::<Vec<String>>();
+        assert_eq!(
+            listed,
+            vec![
+                "!secret-plans",
+                "#general",
+                "#random",
+                "&alice, &carol",
+                "@alice",
+                "@bob"
+            ]
+        );
+    }
+
+    #[test]
+    fn mpim_json_includes_names_and_member_ids() {
+        let conversation = NormalizedConversation {
+            id: "G0123456789".to_string(),
+            type_identifier: "&".to_string(),
+            names: vec!["alice".to_string(), "bob".to_string()],
+            normalized_names: vec!["alice".to_string(), "bob".to_string()],
+            created: 1575000000,
+            is_archived: false,
+            is_deleted: false,
+            is_bot: false,
+            is_member: true,
+            is_general: false,
+            is_shared: false,
+            is_ext_shared: false,
+            is_org_shared: false,
+            show_shared: false,
+            topic: None,
+            purpose: None,
+            member_ids: vec!["U0000000001".to_string(), "U0000000002".to_string()],
+            num_members: None,
+            last_read: None,
+            has_unread: None,
+            creator_id: None,
+            creator: None,
+            is_creator_deleted: false,
+            parent_id: None,
+            parent: None,
+            url: None,
+            similar_to: vec![],
+        };
+
+        let json = serde_json::to_value(&conversation).unwrap();
+        assert_eq!(json["names"], serde_json::json!(["alice", "bob"]));
+        assert_eq!(
+            json["member_ids"],
+            serde_json::json!(["U0000000001", "U0000000002"])
+        );
+    }
+
+    fn level(args: &[&str]) -> LevelFilter {
+        log_level(&build_cli().get_matches_from(args))
+    }
+
+    #[test]
+    fn verbosity_flags_set_log_level() {
+        assert_eq!(level(&["tidy_slack", "ls"]), LevelFilter::Info);
+        assert_eq!(level(&["tidy_slack", "-v", "ls"]), LevelFilter::Debug);
+        assert_eq!(level(&["tidy_slack", "-vv", "ls"]), LevelFilter::Trace);
+        assert_eq!(level(&["tidy_slack", "-q", "ls"]), LevelFilter::Warn);
+        assert_eq!(level(&["tidy_slack", "-qq", "ls"]), LevelFilter::Error);
+        assert_eq!(level(&["tidy_slack", "-qqq", "ls"]), LevelFilter::Off);
+        assert_eq!(level(&["tidy_slack", "--silent", "ls"]), LevelFilter::Off);
+    }
+
+    #[test]
+    fn conflicting_verbosity_flags_are_rejected() {
+        assert!(build_cli()
+            .get_matches_from_safe(&["tidy_slack", "-q", "-v", "ls"])
+            .is_err());
+        assert!(build_cli()
+            .get_matches_from_safe(&["tidy_slack", "--silent", "-v", "ls"])
+            .is_err());
+    }
+
+    #[test]
+    fn ls_parses_types_and_substring() {
+        let options = build_cli().get_matches_from(&[
+            "tidy_slack",
+            "ls",
+            "--types",
+            "public_channel",
+            "im",
+            "--",
+            "general",
+        ]);
+        let ls = options.subcommand_matches("ls").unwrap();
+
+        assert_eq!(
+            ls.values_of_lossy("types").unwrap(),
+            vec!["public_channel", "im"]
+        );
+        assert_eq!(ls.value_of("SUBSTRING"), Some("general"));
+    }
+
+    #[test]
+    fn all_types_expand_to_each_type_once() {
+        let types = |types: &[&str]| {
+            expand_types(
+                &types
+                    .iter()
+                    .map(|kind| kind.to_string())
+                    .collect::<Vec<String>>(),
+            )
+        };
+        assert_eq!(types(&["all"]), TYPES);
+        assert_eq!(types(&["im", "all", "mpim"]), TYPES);
+        assert_eq!(
+            types(&["im", "public_channel", "im"]),
+            ["public_channel", "im"]
+        );
+        assert!(validate_type("all".to_string()).is_ok());
+    }
+
+    #[test]
+    fn type_typos_suggest_the_closest_type() {
+        assert!(validate_type("mpim".to_string()).is_ok());
+        assert!(validate_type("public_channels".to_string())
+            .unwrap_err()
+            .contains("Did you mean `public_channel`?"));
+        assert!(validate_type("dm".to_string())
+            .unwrap_err()
+            .contains("Did you mean `im`?"));
+        assert!(!validate_type("conversations".to_string())
+            .unwrap_err()
+            .contains("Did you mean"));
+    }
+
+    #[test]
+    fn read_requires_channel_or_all() {
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "read"])
+            .is_err());
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "read", "--all", "#general"])
+            .is_err());
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "read", "--all"])
+            .is_ok());
+    }
+
+    #[test]
+    fn head_conflicts_with_tail() {
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "ls", "--head", "5", "--tail", "5"])
+            .is_err());
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "ls", "--tail", "5"])
+            .is_ok());
+    }
+
+    #[test]
+    fn no_im_conflicts_with_types() {
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "ls", "--no-im", "--types", "im"])
+            .is_err());
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "ls", "--no-im", "--no-mpim"])
+            .is_ok());
+    }
+
+    #[test]
+    fn ls_rejects_unknown_types() {
+        assert!(build_cli()
+            .get_matches_from_safe(&["tidy_slack", "ls", "--types", "channels"])
+            .is_err());
+    }
+
+    #[test]
+    fn pretty_requires_json() {
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "ls", "--pretty"])
+            .is_err());
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "ls", "--json", "--pretty"])
+            .is_ok());
+    }
+
+    #[test]
+    fn durations_are_split_into_larger_units() {
+        assert_eq!(
+            format_duration(Duration::from_millis(120_500), Locale::en),
+            "2m 0.5s"
+        );
+        assert_eq!(
+            format_duration(Duration::from_millis(3_603_000), Locale::en),
+            "1h 0m 3s"
+        );
+        assert_eq!(
+            format_duration(Duration::from_millis(5), Locale::en),
+            "0.005s"
+        );
+        assert_eq!(
+            format_duration(Duration::from_secs(3_600_000), Locale::en),
+            "1,000h 0m 0s"
+        );
+    }
+
+    #[test]
+    fn seconds_keep_leading_zeros_in_milliseconds() {
+        assert_eq!(
+            format_seconds(Duration::from_millis(1_234_050), Locale::en),
+            "1,234.050s"
+        );
+        assert_eq!(
+            format_seconds(Duration::from_millis(5), Locale::en),
+            "0.005s"
+        );
+    }
+
+    #[test]
+    fn command_line_overrides_config() {
+        let options = build_cli().get_matches_from(["tidy_slack", "--timeout", "10", "ls"]);
+        assert_eq!(configured(&options, "timeout", Some(60u64)), 10);
+        assert_eq!(configured(&options, "max_retries", Some(5u32)), 5);
+        assert_eq!(configured(&options, "concurrency", None::<usize>), 4);
+    }
+
+    #[test]
+    fn api_base_must_be_a_web_url() {
+        let options = build_cli()
+            .get_matches_from_safe(["tidy_slack", "ls"])
+            .unwrap();
+        assert_eq!(options.value_of("api_base"), Some(slack::API_BASE));
+
+        assert!(build_cli()
+            .get_matches_from_safe([
+                "tidy_slack",
+                "--api-base",
+                "http://localhost:8080/api",
+                "ls"
+            ])
+            .is_ok());
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "--api-base", "slack.com/api", "ls"])
+            .is_err());
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "--api-base", "ftp://example.com/", "ls"])
+            .is_err());
+    }
+
+    #[test]
+    fn max_pages_must_be_positive() {
+        let options = build_cli().get_matches_from(["tidy_slack", "ls"]);
+        assert_eq!(options.value_of("max_pages"), None);
+
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "--max-pages", "0", "ls"])
+            .is_err());
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "--max-pages", "2", "ls"])
+            .is_ok());
+    }
+
+    #[test]
+    fn retried_errors_default_to_transient_ones() {
+        let options = build_cli().get_matches_from(["tidy_slack", "ls"]);
+        assert_eq!(
+            options.values_of_lossy("retry_on_error").unwrap(),
+            vec![
+                "service_unavailable",
+                "internal_error",
+                "fatal_error",
+                "request_timeout"
+            ]
+        );
+
+        let options = build_cli().get_matches_from([
+            "tidy_slack",
+            "--retry-on-error",
+            "internal_error",
+            "ls",
+        ]);
+        assert_eq!(
+            options.values_of_lossy("retry_on_error").unwrap(),
+            vec!["internal_error"]
+        );
+    }
+
+    #[test]
+    fn purge_requires_older_than() {
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "purge", "--dry-run"])
+            .is_err());
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "purge", "--older-than", "90d", "--dry-run"])
+            .is_ok());
+    }
 
-    // Default log level is Info
-    // --silent switches level to Off
-    // -v, --verbose increases level to debug
-    // -vv increases level to Trace
-    // -q, --quiet decreases level to Error
-    // -qq decreases level to Off
-    let filter: LevelFilter = if options.is_present("silent") {
-        LevelFilter::Off
-    } else {
-        match options.occurrences_of("verbose") {
-            0 => match options.occurrences_of("quiet") {
-                3..=u64::MAX => LevelFilter::Off,
-                2 => LevelFilter::Error,
-                1 => LevelFilter::Warn,
-                0 => LevelFilter::Info, // Default
-            },
-            1 => LevelFilter::Debug,
-            2..=u64::MAX => LevelFilter::Trace,
+    #[test]
+    fn null_requires_print_ids() {
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "ls", "--null"])
+            .is_err());
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "ls", "--print-ids", "--null"])
+            .is_ok());
+    }
+
+    fn message(user: Option<&str>, ts: &str) -> Message {
+        Message {
+            kind: "message".to_string(),
+            subtype: None,
+            user: user.map(|user| user.to_string()),
+            text: "".to_string(),
+            ts: ts.to_string(),
+            thread_ts: None,
+            reply_count: None,
         }
-    };
+    }
 
-    env_logger::Builder::from_default_env()
-        .filter(Some(module_path!()), filter)
-        .init();
+    #[tokio::test]
+    async fn filters_messages_by_author_id_or_name() {
+        let messages = || {
+            vec![
+                message(Some("U0000000001"), "1"),
+                message(Some("U0000000002"), "2"),
+                message(None, "3"),
+                message(Some("U0000000001"), "4"),
+            ]
+        };
+        let slack = mock_slack();
 
-    if let Some(cmd) = options.subcommand_name() {
-        let sub_options = options.subcommand_matches(cmd);
-        match cmd {
-            "ls" => ls(types, sub_options),
-            _ => panic!("Unsupported command: {}", cmd),
+        for author in &["U0000000001", "alice", "@alice"] {
+            let authors = resolve_author(&slack, author).await.unwrap();
+            let filtered = filter_by_author(messages(), &authors)
+                .into_iter()
+                .map(|message| message.ts)
+                .collect::<Vec<String>>();
+            assert_eq!(filtered, vec!["1", "4"]);
         }
-    };
+        assert!(resolve_author(&slack, "mallory").await.is_err());
+    }
 
-    info!(
-        "Command completed in {}.{}s",
-        now.elapsed().as_secs().to_formatted_string(&Locale::en),
-        now.elapsed().subsec_millis()
-    );
-}
+    #[tokio::test]
+    async fn continues_past_failed_deletions_by_default() {
+        let messages = vec![
+            message(None, "1"),
+            message(None, "failing"),
+            message(None, "2"),
+        ];
+        let mut tally = Tally::default();
+        delete_messages(
+            &mock_slack(),
+            "C0000000001",
+            messages,
+            &ProgressBar::hidden(),
+            false,
+            &mut tally,
+            &mut deletion_pacer(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            tally,
+            Tally {
+                deleted: 2,
+                skipped: 0,
+                failed: 1
+            }
+        );
+        assert_eq!(
+            tally.summary(Locale::en),
+            "Deleted 2 messages and skipped 0 messages that couldn't be deleted, 1 messages failed to delete"
+        );
+        assert_eq!(
+            tally.rate(Duration::from_secs(30)),
+            "Averaged 6.0 deletions per minute"
+        );
+    }
 
-#[derive(Deserialize, Debug)]
-#[serde(untagged)]
-enum ConversationsKind {
-    Conversations(Conversations),
-    Error(ConversationsError),
-}
+    #[tokio::test]
+    async fn fails_fast_on_failed_deletions() {
+        let messages = vec![
+            message(None, "1"),
+            message(None, "failing"),
+            message(None, "2"),
+        ];
+        let mut tally = Tally::default();
+        let error = delete_messages(
+            &mock_slack(),
+            "C0000000001",
+            messages,
+            &ProgressBar::hidden(),
+            true,
+            &mut tally,
+            &mut deletion_pacer(),
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().starts_with(
+            "Couldn't delete message failing from C0000000001 after deleting 1 messages"
+        ));
+        assert_eq!(tally.deleted, 1);
+        assert_eq!(tally.failed, 1);
+    }
 
-#[derive(Deserialize, Debug)]
-struct ConversationsError {
-    ok: bool,
-    error: String,
-}
-impl std::fmt::Display for ConversationsError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.error)
+    #[test]
+    fn silent_runs_confirm_without_asking() {
+        assert!(confirm(
+            "Delete 3 messages from C0000000001?",
+            LevelFilter::Off
+        ));
     }
-}
-impl Error for ConversationsError {}
 
-#[derive(Deserialize, Debug)]
-struct Conversations {
-    ok: bool,
-    warning: Option<String>,
-    channels: Vec<Conversation>,
-    response_metadata: Metadata,
-}
+    #[test]
+    fn count_only_conflicts_with_dry_run() {
+        assert!(build_cli()
+            .get_matches_from_safe([
+                "tidy_slack",
+                "purge",
+                "--older-than",
+                "30d",
+                "--count-only",
+                "--dry-run",
+            ])
+            .is_err());
+        assert!(build_cli()
+            .get_matches_from_safe([
+                "tidy_slack",
+                "rm",
+                "--count-only",
+                "--from",
+                "alice",
+                "#general"
+            ])
+            .is_ok());
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "rm", "--count-only", "--dry-run", "#general"])
+            .is_err());
+    }
 
-#[derive(Deserialize, Debug)]
-#[serde(untagged)]
-enum Conversation {
-    PublicChannel(PublicChannel),
-    PrivateChannel(PrivateChannel),
-    Im(Im),
-}
+    #[test]
+    fn update_is_an_alias_for_edit() {
+        let options = build_cli()
+            .get_matches_from_safe([
+                "tidy_slack",
+                "update",
+                "#general",
+                "1575000000.000100",
+                "--text",
+                "[removed]",
+            ])
+            .unwrap();
+        let edit = options.subcommand_matches("edit").unwrap();
+        assert_eq!(edit.value_of("TS"), Some("1575000000.000100"));
+        assert_eq!(edit.value_of("text"), Some("[removed]"));
+    }
 
-#[derive(Deserialize, Debug)]
-struct PublicChannel {
-    id: String,
-    name: String,
-    is_channel: bool,
-    is_group: bool,
-    is_im: bool,
-    created: u64,
-    is_archived: bool,
-    is_general: bool,
-    unlinked: u64,
-    name_normalized: String,
-    // is_read_only: bool,
-    is_shared: bool,
-    parent_conversation: Option<String>,
-    creator: String,
-    is_ext_shared: bool,
-    is_org_shared: bool,
-    shared_team_ids: Vec<String>, // Not in documentation, but shows up in results
-    pending_shared: Vec<String>,  // I believe this should always be an empty array?
-    pending_connected_team_ids: Vec<String>, // Not in documentation, but shows up in results
-    is_pending_ext_shared: bool,
-    is_member: bool,
-    is_private: bool,
-    is_mpim: bool,
-    last_read: Option<String>,
-    is_open: Option<bool>,
-    topic: Topic,
-    purpose: Purpose,
-    previous_names: Vec<String>,
-    num_members: u64,
-    priority: Option<u64>,
-    // locale: String
-}
+    #[test]
+    fn edit_rejects_empty_text() {
+        assert!(build_cli()
+            .get_matches_from_safe([
+                "tidy_slack",
+                "edit",
+                "#general",
+                "1575000000.000100",
+                "--text",
+                "",
+            ])
+            .is_err());
+    }
 
-#[derive(Deserialize, Debug)]
-struct PrivateChannel {
-    id: String,
-    name: String,
-    is_channel: bool,
-    is_group: bool,
-    is_im: bool,
-    created: u64,
-    is_archived: bool,
-    is_general: bool,
-    unlinked: u64,
-    name_normalized: String,
-    is_read_only: Option<bool>, // I'm not seeing this in the response, but it's in documentation, so I made it optional
-    is_shared: bool,
-    parent_conversation: Option<String>,
-    creator: String,
-    is_ext_shared: bool,
-    is_org_shared: bool,
-    shared_team_ids: Vec<String>, // Not in documentation, but shows up in results
-    pending_shared: Vec<String>,  // I believe this should always be an empty array?
-    pending_connected_team_ids: Vec<String>, // Not in documentation, but shows up in results
-    is_pending_ext_shared: bool,
-    is_member: bool,
-    is_private: bool,
-    is_mpim: bool,
-    last_read: Option<String>,
-    is_open: Option<bool>,
-    topic: Topic,
-    purpose: Purpose,
-    priority: u64,
-    locale: Option<String>, // I'm not seeing this in the response, but it's in documentation, so I made it optional
-}
+    #[tokio::test]
+    async fn links_to_conversations_in_the_workspace() {
+        assert_eq!(
+            archive_url("https://example.slack.com", "C0000000001"),
+            "https://example.slack.com/archives/C0000000001"
+        );
 
-#[derive(Deserialize, Debug)]
-struct Im {
-    id: String,
-    created: u64,
-    is_archived: bool,
-    is_im: bool,
-    is_org_shared: bool,
-    user: String,
-    is_user_deleted: bool,
-    priority: u64,
-}
+        let slack = mock_slack();
+        let (listed, csv, csv_with_url) = future::join3(
+            ls_output(&slack, &["--show-url"]),
+            ls_output(&slack, &["--format", "csv"]),
+            ls_output(&slack, &["--format", "csv", "--fields", "id,url"]),
+        )
+        .await;
+        assert!(listed
+            .lines()
+            .any(|line| line.ends_with("#general https://example.slack.com/archives/C0000000001")));
+        assert!(csv.starts_with("id,type,name,created,members,archived\n"));
+        assert!(!csv.contains("https://"));
+        assert!(csv_with_url
+            .lines()
+            .any(|line| line == "C0000000001,https://example.slack.com/archives/C0000000001"));
+    }
 
-#[derive(Deserialize, Debug)]
-struct Topic {
-    value: Option<String>,
-    creator: Option<String>,
-    last_set: Option<u64>,
-}
+    #[test]
+    fn empty_listings_say_so() {
+        let mut out = vec![];
+        print_empty(&mut out, &[], false).unwrap();
+        print_empty(&mut out, &["general", "random"], false).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "No conversations found\nNo conversations match `general` or `random`\n"
+        );
+    }
 
-#[derive(Deserialize, Debug)]
-struct Purpose {
-    value: Option<String>,
-    creator: Option<String>,
-    last_set: Option<u64>,
-}
+    #[tokio::test]
+    async fn exclude_deleted_users_drops_their_dms() {
+        let mut slack = mock_slack();
+        slack
+            .users
+            .insert("U0000000004", user_json("U0000000004", "dave", true));
+        slack.conversations.push(
+            im_json("D0000000004", "U0000000004")
+                .replace(r#""is_user_deleted": false"#, r#""is_user_deleted": true"#),
+        );
 
-#[derive(Deserialize, Debug)]
-struct Metadata {
-    next_cursor: String,
-}
+        let (all, excluded) = future::join(
+            ls_output(&slack, &["--print-ids"]),
+            ls_output(&slack, &["--print-ids", "--exclude-deleted-users"]),
+        )
+        .await;
+        assert!(all.lines().any(|id| id == "D0000000004"));
+        assert!(!excluded.lines().any(|id| id == "D0000000004"));
+        assert_eq!(excluded.lines().count(), all.lines().count() - 1);
+    }
 
-fn get_token() -> Result<String, Box<dyn Error>> {
-    Ok(fs::read_to_string("TOKEN")?
-        .parse::<String>()?
-        .trim()
-        .to_string())
-}
+    #[tokio::test]
+    async fn json_lines_prints_one_conversation_per_line() {
+        let slack = mock_slack();
+        let output = ls_output(&slack, &["--json-lines"]).await;
 
-fn get_conversations(
-    enabled_types: Vec<String>,
-    exclude_archived: bool,
-) -> Result<Vec<Conversation>, Box<dyn Error>> {
-    let mut cursor = "".to_string();
-    let mut conversations = vec![];
-    let enabled_types = &enabled_types.join(",");
-    loop {
-        let mut result = get_conversations_page(enabled_types, exclude_archived, &cursor)?;
-        cursor = result.response_metadata.next_cursor;
-        conversations.append(&mut result.channels);
-        if cursor == "" {
-            break;
-        }
+        let mut ids = output
+            .lines()
+            .map(|line| {
+                let conversation = serde_json::from_str::<serde_json::Value>(line).unwrap();
+                conversation["id"].as_str().unwrap().to_string()
+            })
+            .collect::<Vec<String>>();
+        ids.sort_unstable();
+        assert_eq!(
+            ids,
+            [
+                "C0000000001",
+                "C0000000002",
+                "D0000000001",
+                "D0000000002",
+                "G0000000001",
+                "G0000000002"
+            ]
+        );
     }
 
-    return Ok(conversations);
-}
+    #[tokio::test]
+    async fn empty_workspaces_list_nothing() {
+        let slack = MockSlack {
+            conversations: vec![],
+            users: HashMap::new(),
+            messages: HashMap::new(),
+            deleted: RefCell::new(vec![]),
+        };
 
-fn get_conversations_page(
-    enabled_types: &str,
-    exclude_archived: bool,
-    cursor: &str,
-) -> Result<Conversations, Box<dyn Error>> {
-    let mut response = Client::new()
-        .get("https://slack.com/api/conversations.list")
-        .query(&[
-            ("cursor", cursor),
-            (
-                "exclude_archived",
-                if exclude_archived { "true" } else { "false" },
-            ),
-            ("limit", "1000"),
-            // public_channel: #channel
-            // private_channel: 🔒channel
-            // mpim: 🧑🧑multi-person-direct-message
-            // im: 🧑direct-message
-            ("types", enabled_types),
-        ])
-        .header("Authorization", get_token()?)
-        .send()?;
+        let (listed, streamed) =
+            future::join(ls_output(&slack, &[]), ls_output(&slack, &["--stream"])).await;
+        assert_eq!(listed, "No conversations found\n");
+        // Streams print the header before knowing whether anything will follow
+        assert_eq!(
+            streamed,
+            "All conversations you have access to:\nNo conversations found\n"
+        );
+    }
+
+    #[test]
+    fn parses_numbered_selections() {
+        assert_eq!(parse_selection("1, 3-5,4", 5), Ok(vec![0, 2, 3, 4]));
+        assert_eq!(parse_selection("", 5), Ok(vec![]));
+        assert!(parse_selection("0", 5).is_err());
+        assert!(parse_selection("6", 5).is_err());
+        assert!(parse_selection("4-2", 5).is_err());
+        assert!(parse_selection("general", 5).is_err());
+    }
 
-    let string = response.text()?;
+    #[test]
+    fn interactive_rm_replaces_channel() {
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "rm", "--interactive"])
+            .is_ok());
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "rm", "--interactive", "#general"])
+            .is_err());
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "rm"])
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn files_delete_fails_instead_of_panicking() {
+        let slack = mock_slack();
+        let options = build_cli().get_matches_from(&[
+            "tidy_slack",
+            "--silent",
+            "files",
+            "delete",
+            "F0000000001",
+            "F_MISSING",
+        ]);
+        let options = options.subcommand_matches("files").unwrap();
+        let error = files(&slack, options, LevelFilter::Off, Locale::en)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Couldn't delete file F_MISSING: file_not_found"
+        );
+    }
 
-    // println!("Text: {}", string);
+    #[tokio::test]
+    async fn rm_deletes_from_each_channel() {
+        let mut slack = mock_slack();
+        slack
+            .messages
+            .insert("C0000000002", vec!["1575000000.000100"]);
+        slack.messages.insert(
+            "G0000000002",
+            vec!["1575000000.000200", "1575000000.000300"],
+        );
+        let rm_options = |args: &[&str]| {
+            build_cli().get_matches_from([&["tidy_slack", "--silent", "rm"], args].concat())
+        };
 
-    let result = serde_json::from_str::<ConversationsKind>(&string);
+        let options = rm_options(&["--dry-run", "#random", "#secret-plans"]);
+        let options = options.subcommand_matches("rm").unwrap();
+        let total = rm(&slack, options, LevelFilter::Off, Locale::en, 1)
+            .await
+            .unwrap();
+        assert_eq!(total, Tally::default());
+        assert!(slack.deleted.borrow().is_empty());
 
-    match result? {
-        ConversationsKind::Error(error) => Err(error)?,
-        ConversationsKind::Conversations(conversations) => Ok(conversations),
+        let options = rm_options(&["#random", "#secret-plans"]);
+        let options = options.subcommand_matches("rm").unwrap();
+        let total = rm(&slack, options, LevelFilter::Off, Locale::en, 1)
+            .await
+            .unwrap();
+        assert_eq!(
+            total,
+            Tally {
+                deleted: 3,
+                skipped: 0,
+                failed: 0
+            }
+        );
+        assert_eq!(
+            *slack.deleted.borrow(),
+            [
+                ("C0000000002", "1575000000.000100"),
+                ("G0000000002", "1575000000.000200"),
+                ("G0000000002", "1575000000.000300"),
+            ]
+            .iter()
+            .map(|(conversation, ts)| (conversation.to_string(), ts.to_string()))
+            .collect::<Vec<(String, String)>>()
+        );
     }
-}
 
-#[derive(Deserialize, Debug)]
-#[serde(untagged)]
-enum UserResult {
-    Success(UserSuccess),
-    Error(UserError),
-}
+    #[test]
+    fn rm_accepts_several_channels() {
+        let options = build_cli()
+            .get_matches_from_safe(["tidy_slack", "rm", "#general", "@alice", "C0000000002"])
+            .unwrap();
+        assert_eq!(
+            options
+                .subcommand_matches("rm")
+                .unwrap()
+                .values_of("CHANNEL")
+                .unwrap()
+                .collect::<Vec<&str>>(),
+            ["#general", "@alice", "C0000000002"]
+        );
 
-#[derive(Deserialize, Debug)]
-struct UserSuccess {
-    ok: bool,
-    user: User,
-}
+        let mut total = Tally {
+            deleted: 2,
+            skipped: 1,
+            failed: 0,
+        };
+        total.add(&Tally {
+            deleted: 3,
+            skipped: 0,
+            failed: 1,
+        });
+        assert_eq!(
+            total,
+            Tally {
+                deleted: 5,
+                skipped: 1,
+                failed: 1,
+            }
+        );
+    }
 
-#[derive(Deserialize, Debug)]
-#[serde(untagged)]
-enum User {
-    Active(ActiveUser),
-    Deleted(DeletedUser),
-}
+    #[test]
+    fn fail_fast_conflicts_with_continue() {
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "rm", "--fail-fast", "--continue", "#general"])
+            .is_err());
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "purge", "--older-than", "30d", "--fail-fast"])
+            .is_ok());
 
-#[derive(Deserialize, Debug)]
-struct ActiveUser {
-    id: String,
-    team_id: String,
-    name: String,
-    deleted: bool,
-    color: String,
-    real_name: String,
-    tz: String,
-    tz_label: String,
-    tz_offset: i64,
-    profile: Profile,
-    is_admin: bool,
-    is_owner: bool,
-    is_primary_owner: bool,
-    is_restricted: bool,
-    is_ultra_restricted: bool,
-    is_bot: bool,
-    is_app_user: bool,
-    updated: u64,
-    has_2fa: bool,
-}
+        let fails_fast = |args: &[&str]| {
+            let options = build_cli().get_matches_from([&["tidy_slack", "rm"], args].concat());
+            fail_fast(options.subcommand_matches("rm").unwrap())
+        };
+        assert!(fails_fast(&["--fail-fast", "#general"]));
+        assert!(!fails_fast(&["--continue", "#general"]));
+        assert!(!fails_fast(&["#general"]));
+    }
 
-#[derive(Deserialize, Debug)]
-struct DeletedUser {
-    id: String,
-    team_id: String,
-    name: String,
-    deleted: bool,
-    profile: Profile,
-    is_bot: bool,
-    is_app_user: bool,
-    updated: u64,
-}
+    #[test]
+    fn parses_dates() {
+        assert_eq!(parse_date("1706691600"), Ok(1706691600));
+        assert_eq!(parse_date("2024-01-31"), Ok(1706659200));
+        assert_eq!(parse_date("2024-01-31T09:00:00Z"), Ok(1706691600));
+        assert_eq!(parse_date("2024-01-31T10:00:00+01:00"), Ok(1706691600));
+        assert!(parse_date("1969-12-31").is_err());
+        assert!(parse_date("31/01/2024").is_err());
+    }
 
-#[derive(Deserialize, Debug)]
-struct Profile {
-    title: String, // Not in documentation
-    phone: String, // Not in documentation
-    skype: String, // Not in documentation
-    real_name: String,
-    real_name_normalized: String,
-    display_name: String,
-    display_name_normalized: String,
-    status_text: String,
-    status_emoji: String,
-    status_expiration: u64, // Not in documentation
-    avatar_hash: String,
-    email: Option<String>,          // In documentation, but not response
-    image_original: Option<String>, // In documentation, but not response
-    image_24: String,
-    image_32: String,
-    image_48: String,
-    image_72: String,
-    image_192: String,
-    image_512: String,
-    status_text_canonical: String, // Not in documentation
-    team: String,
-}
+    #[test]
+    fn dates_take_the_place_of_durations() {
+        let options = build_cli()
+            .get_matches_from_safe([
+                "tidy_slack",
+                "export",
+                "--after",
+                "2024-01-31",
+                "--before",
+                "1706745600",
+                "#general",
+            ])
+            .unwrap();
+        assert_eq!(
+            time_window(options.subcommand_matches("export").unwrap()),
+            (Some(1706659200), Some(1706745600))
+        );
 
-#[derive(Deserialize, Debug)]
-struct UserError {
-    ok: bool,
-    error: String,
-}
-impl std::fmt::Display for UserError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.error)
+        assert!(build_cli()
+            .get_matches_from_safe([
+                "tidy_slack",
+                "rm",
+                "--after",
+                "2024-01-31",
+                "--newer-than",
+                "30d",
+                "#general",
+            ])
+            .is_err());
     }
-}
-impl Error for UserError {}
 
-fn get_user(user: String) -> Result<String, Box<dyn Error>> {
-    let mut response = Client::new()
-        .get("https://slack.com/api/users.info")
-        .query(&[("user", user)])
-        .header("Authorization", get_token()?)
-        .send()?;
+    #[test]
+    fn saves_messages_to_sqlite_once() {
+        let mut connection = Connection::open_in_memory().unwrap();
+        let messages = vec![message(Some("U0000000001"), "1"), message(None, "2")];
+        save_messages(&mut connection, "C0000000001", &messages).unwrap();
+        save_messages(&mut connection, "C0000000001", &messages).unwrap();
+
+        let mut select = connection
+            .prepare("SELECT ts, user, channel FROM messages ORDER BY ts")
+            .unwrap();
+        let rows = select
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<(String, Option<String>, String)>>>()
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                (
+                    "1".to_string(),
+                    Some("U0000000001".to_string()),
+                    "C0000000001".to_string()
+                ),
+                ("2".to_string(), None, "C0000000001".to_string()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn filters_by_member_count() {
+        let conversations = normalized(&mock_slack()).await;
+        let between = |min, max| {
+            conversations
+                .iter()
+                .filter(|convo| convo.has_members_between(min, max))
+                .count()
+        };
 
-    let string = response.text()?;
+        assert_eq!(between(None, None), 6);
+        assert_eq!(between(Some(3), None), 2);
+        assert_eq!(between(None, Some(3)), 2);
+        assert_eq!(between(Some(4), None), 0);
+        assert_eq!(between(None, Some(2)), 0);
+    }
+
+    #[test]
+    fn footer_counts_each_type() {
+        let mut counts = HashMap::new();
+        counts.insert("public_channel", 1234);
+        counts.insert("im", 2);
 
-    let result = serde_json::from_str::<UserResult>(&string);
+        let mut out = vec![];
+        print_footer(&mut out, &counts, Locale::en).unwrap();
 
-    match result? {
-        UserResult::Error(error) => Err(error)?,
-        UserResult::Success(result) => Ok(match result.user {
-            User::Active(user) => user.name,
-            User::Deleted(user) => user.name,
-        }),
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\npublic_channel: 1,234\nprivate_channel: 0\nmpim: 0\nim: 2\n"
+        );
     }
-}
 
-fn ls(types: [&str; 4], options: Option<&ArgMatches>) {
-    let style = ProgressStyle::default_bar()
-        .template(
-            "{elapsed_precise} [{bar:40}] {pos:>7}/{len:7}\n           {prefix}\n           {msg}",
+    #[tokio::test]
+    async fn info_shows_details_of_one_conversation() {
+        let slack = mock_slack();
+        let mut conversation = normalize_conversation(
+            &slack,
+            slack.get_conversation("C0000000001").await.unwrap(),
+            false,
+            ProfileField::Name,
+            &ProgressBar::hidden(),
         )
-        .progress_chars("=> ");
+        .await;
+        resolve_creator(
+            &slack,
+            &mut conversation,
+            ProfileField::Name,
+            &ProgressBar::hidden(),
+        )
+        .await;
 
-    let length = 4;
-    let main_progress = ProgressBar::new(length);
-    main_progress.set_style(style.clone());
+        let mut out = vec![];
+        print_info(&mut out, &conversation, Locale::en).unwrap();
 
-    main_progress.set_prefix("Retrieving all conversations...");
+        assert_eq!(
+            uncolored(out),
+            "ID:      C0000000001\nName:    #general\nType:    public_channel\nCreated: 2019-11-29\nStatus:  active\nMember:  yes\nMembers: 3\nCreator: @alice\nTopic:   -\nPurpose: -\n"
+        );
+    }
 
-    let enabled_types;
-    let mut substring = "";
-    let mut exclude_archived = false;
-    if let Some(options) = options {
-        enabled_types = if let Some(specified_types) = options.values_of_lossy("types") {
-            specified_types
-        } else {
-            types.to_vec().iter().map(|s| s.to_string()).collect()
-        };
-        if options.is_present("exclude_archived") {
-            exclude_archived = true;
-        }
-        if let Some(provided_substring) = options.value_of("SUBSTRING") {
-            substring = provided_substring;
+    #[tokio::test]
+    async fn deletes_without_details_slack_cant_find() {
+        let slack = mock_slack();
+        let options = build_cli().get_matches_from(["tidy_slack", "rm", "C0000000009"]);
+        let options = options.subcommand_matches("rm").unwrap();
+        let scopes = vec!["channels:history".to_string()];
+
+        let (is_ext_shared, messages) =
+            deletable_messages(&slack, "C0000000009", Some(&scopes), None, options)
+                .await
+                .unwrap();
+        assert!(!is_ext_shared);
+        assert!(messages.is_empty());
+        // Known conversations are still checked for missing scopes
+        assert!(
+            deletable_messages(&slack, "G0000000002", Some(&scopes), None, options)
+                .await
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn only_member_leaves_out_other_conversations() {
+        let mut slack = mock_slack();
+        slack.conversations.push(
+            public_channel_json("C0000000003", "announcements")
+                .replace(r#""is_member": true"#, r#""is_member": false"#),
+        );
+
+        let (all, member) = future::join(
+            ls_output(&slack, &["--print-ids"]),
+            ls_output(&slack, &["--print-ids", "--only-member"]),
+        )
+        .await;
+        assert!(all.lines().any(|id| id == "C0000000003"));
+        assert!(!member.lines().any(|id| id == "C0000000003"));
+        assert_eq!(member.lines().count(), all.lines().count() - 1);
+    }
+
+    #[tokio::test]
+    async fn resolves_names_to_ids() {
+        let slack = mock_slack();
+
+        let resolve = |conversation| resolve_conversation(&slack, conversation);
+        assert_eq!(resolve("C0000000002").await.unwrap(), "C0000000002");
+        assert_eq!(resolve("#general").await.unwrap(), "C0000000001");
+        assert_eq!(resolve("#secret-plans").await.unwrap(), "G0000000002");
+        assert_eq!(resolve("@bob").await.unwrap(), "D0000000002");
+        assert_eq!(
+            resolve("&mpdm-carol--alice-1").await.unwrap(),
+            "G0000000001"
+        );
+        assert_eq!(resolve("&alice,carol").await.unwrap(), "G0000000001");
+        assert!(resolve("&alice").await.is_err());
+        assert!(resolve("@secret-plans").await.is_err());
+        assert!(resolve("#missing").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn ambiguous_names_are_rejected() {
+        let mut slack = mock_slack();
+        slack
+            .conversations
+            .push(private_channel_json("G0000000003", "general", false));
+
+        let error = resolve_conversation(&slack, "#general")
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("C0000000001"));
+        assert!(error.contains("G0000000003"));
+    }
+
+    #[tokio::test]
+    async fn finds_general_channel() {
+        let slack = mock_slack();
+        assert_eq!(
+            find_general(&slack).await.unwrap().as_deref(),
+            Some("C0000000001")
+        );
+
+        let general = normalized(&slack)
+            .await
+            .into_iter()
+            .filter(|conversation| conversation.is_general)
+            .map(|conversation| conversation.id)
+            .collect::<Vec<String>>();
+        assert_eq!(general, vec!["C0000000001"]);
+    }
+
+    #[tokio::test]
+    async fn marks_channels_with_newer_messages_as_unread() {
+        let slack = mock_slack();
+        let mut conversations = normalized(&slack).await;
+        for conversation in &mut conversations {
+            check_unread(&slack, conversation, &ProgressBar::hidden()).await;
         }
-    } else {
-        enabled_types = types.to_vec().iter().map(|s| s.to_string()).collect();
-    };
 
-    let raw_conversations = get_conversations(enabled_types, exclude_archived).unwrap();
+        let channel = conversations
+            .iter()
+            .find(|convo| convo.id == "C0000000001")
+            .unwrap();
+        assert_eq!(channel.has_unread, Some(true));
+        // The latest message of #random can't be retrieved, so it's left unknown
+        let failed = conversations
+            .iter()
+            .find(|convo| convo.id == "C0000000002")
+            .unwrap();
+        assert_eq!(failed.has_unread, None);
+        let im = conversations
+            .iter()
+            .find(|convo| convo.id == "D0000000001")
+            .unwrap();
+        assert_eq!(im.has_unread, None);
 
-    std::thread::sleep(std::time::Duration::new(5, 0));
+        let latest = message(None, "1575000000.000100");
+        assert!(!is_unread("1575000000.000100", Some(&latest)));
+        assert!(!is_unread("1575000000.000100", None));
+    }
 
-    main_progress.inc(1);
-    main_progress.set_prefix("Retrieving metadata and normalizing conversations...");
-    main_progress.set_length(raw_conversations.len() as u64 + length);
+    #[test]
+    fn locale_must_be_supported() {
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "--locale", "de", "ls"])
+            .is_ok());
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "--locale", "klingon", "ls"])
+            .is_err());
+    }
 
-    let mut conversations = vec![];
-    for conversation in raw_conversations {
-        match conversation {
-            Conversation::PublicChannel(convo) => {
-                main_progress.set_message(&format!("Normalizing #{}", convo.name));
-                conversations.push(NormalizedConversation {
-                    id: convo.id,
-                    type_identifier: "#".to_string(),
-                    names: vec![convo.name],
-                    is_archived: convo.is_archived,
-                    is_deleted: false,
-                });
-            }
-            Conversation::PrivateChannel(mut convo) => {
-                if convo.name.starts_with("mpdm-") {
-                    main_progress.set_message("Normalizing conversation with multiple members");
-                    conversations.push(NormalizedConversation {
-                        id: convo.id,
-                        type_identifier: "&".to_string(),
-                        names: convo
-                            .name
-                            .split_off(5)
-                            .rsplitn(2, "-")
-                            .last()
-                            .unwrap()
-                            .split("--")
-                            .map(|s| s.to_string())
-                            .collect(),
-                        is_archived: convo.is_archived,
-                        is_deleted: false,
-                    });
-                } else {
-                    main_progress
-                        .set_message(&format!("Normalizing private channel #{}", convo.name));
-                    conversations.push(NormalizedConversation {
-                        id: convo.id,
-                        type_identifier: "!".to_string(),
-                        names: vec![convo.name],
-                        is_archived: convo.is_archived,
-                        is_deleted: false,
-                    });
-                }
-            }
-            Conversation::Im(convo) => {
-                main_progress.set_message(&format!("Retrieving metadata for user {}", convo.user));
-                let name = get_user(convo.user).unwrap();
-                main_progress.tick();
-                main_progress.set_message(&format!("Normalizing conversation with @{}", name));
-                conversations.push(NormalizedConversation {
-                    id: convo.id,
-                    type_identifier: "@".to_string(),
-                    names: vec![name],
-                    is_archived: convo.is_archived,
-                    is_deleted: convo.is_user_deleted,
-                });
-            }
+    #[tokio::test]
+    async fn table_prints_selected_fields_in_order() {
+        let mut conversations = normalized(&mock_slack())
+            .await
+            .into_iter()
+            .filter(|convo| convo.type_identifier == "#")
+            .collect::<Vec<NormalizedConversation>>();
+        conversations.sort_unstable();
+
+        let mut out = vec![];
+        print_table(&mut out, &conversations, Some(&["name", "members", "id"])).unwrap();
+
+        assert_eq!(
+            uncolored(out),
+            "🗒 #general 3 C0000000001\n🗒 #random  3 C0000000002\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn groups_channels_with_similar_names() {
+        let mut slack = mock_slack();
+        for (id, name) in &[
+            ("C0000000003", "ran-dom"),
+            ("C0000000004", "dev-ops"),
+            ("C0000000005", "devil"),
+            ("C0000000006", "devops"),
+        ] {
+            slack.conversations.push(public_channel_json(id, name));
         }
-        main_progress.inc(1);
+        let mut conversations = normalized(&slack).await;
+        conversations.sort_unstable();
+
+        let grouped = group_duplicates(conversations)
+            .into_iter()
+            .filter(|convo| convo.type_identifier == "#")
+            .map(|convo| format!("{}{}", convo.display_names(), convo.annotations()))
+            .collect::<Vec<String>>();
+        assert_eq!(
+            grouped,
+            [
+                "#dev-ops (similar to #devops)",
+                "#devops (similar to #dev-ops)",
+                "#devil",
+                "#general",
+                "#ran-dom (similar to #random)",
+                "#random (similar to #ran-dom)",
+            ]
+        );
     }
 
-    if substring != "" {
-        main_progress.set_prefix(&format!(
-            "Filtering conversations down to those that contain `{}`...",
-            substring
-        ));
+    #[tokio::test]
+    async fn groups_types_in_provided_order() {
+        let mut conversations = normalized(&mock_slack()).await;
+        conversations.sort_unstable_by(|a, b| cmp_by_type_order(a, b, &["im", "mpim"]));
 
-        conversations = conversations
+        let mut identifiers = conversations
+            .iter()
+            .map(|convo| convo.type_identifier.as_str())
+            .collect::<Vec<&str>>();
+        identifiers.dedup();
+        assert_eq!(identifiers, ["@", "&", "!", "#"]);
+    }
+
+    #[test]
+    fn json_lines_conflicts_with_json() {
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "ls", "--json-lines", "--include-members"])
+            .is_ok());
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "ls", "--json-lines", "--json"])
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn ids_are_newline_or_null_terminated() {
+        let mut conversations = normalized(&mock_slack())
+            .await
             .into_iter()
-            .filter(|convo| {
-                for name in &convo.names {
-                    if name.contains(substring) {
-                        return true;
-                    }
-                }
-                false
-            })
+            .filter(|convo| convo.type_identifier == "#")
             .collect::<Vec<NormalizedConversation>>();
+        conversations.sort_unstable();
+
+        let mut lines = vec![];
+        let mut nulls = vec![];
+        for conversation in &conversations {
+            print_id(&mut lines, conversation, false).unwrap();
+            print_id(&mut nulls, conversation, true).unwrap();
+        }
+
+        assert_eq!(
+            String::from_utf8(lines).unwrap(),
+            "C0000000001\nC0000000002\n"
+        );
+        assert_eq!(
+            String::from_utf8(nulls).unwrap(),
+            "C0000000001\0C0000000002\0"
+        );
     }
 
-    main_progress.inc(1);
-    main_progress.set_prefix("Sorting names in multi-person DMs...");
+    #[tokio::test]
+    async fn csv_quotes_names_with_commas() {
+        let slack = mock_slack();
+        let mut conversations = normalized(&slack)
+            .await
+            .into_iter()
+            .filter(|convo| convo.type_identifier == "&" || convo.id == "C0000000001")
+            .collect::<Vec<NormalizedConversation>>();
+        conversations.sort_unstable();
 
-    for conversation in &mut conversations {
-        conversation.names.sort_unstable();
+        let mut out = vec![];
+        print_csv(&mut out, &conversations, Some(&["id", "name", "members"])).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "id,name,members\nC0000000001,general,3\nG0000000001,\"carol, alice\",-\n"
+        );
+        assert_eq!(csv_quote(r#"say "hi""#), r#""say ""hi""""#);
     }
 
-    main_progress.inc(1);
-    main_progress.set_prefix("Sorting conversations by type and name...");
+    #[test]
+    fn fields_are_comma_separated_and_validated() {
+        let options =
+            build_cli().get_matches_from(["tidy_slack", "ls", "--fields", "name,id", "general"]);
+        let ls = options.subcommand_matches("ls").unwrap();
+        assert_eq!(ls.values_of_lossy("fields").unwrap(), vec!["name", "id"]);
+        assert_eq!(ls.value_of("SUBSTRING"), Some("general"));
 
-    conversations.sort_unstable_by(|a, b| a.names.partial_cmp(&b.names).unwrap());
-    conversations.sort_by(|a, b| a.type_identifier.partial_cmp(&b.type_identifier).unwrap());
+        assert!(build_cli()
+            .get_matches_from_safe(["tidy_slack", "ls", "--fields", "name,colour"])
+            .is_err());
+    }
 
-    main_progress.inc(1);
-    main_progress.finish_and_clear();
+    #[test]
+    fn ls_accepts_multiple_substrings() {
+        let options =
+            build_cli().get_matches_from(["tidy_slack", "ls", "proj", "2024", "--match", "all"]);
+        let ls = options.subcommand_matches("ls").unwrap();
 
-    if substring != "" {
-        println!("All conversations you have access to:");
-    } else {
-        println!(
-            "All conversations with names that contain `{}` that you have access to:",
-            substring
+        assert_eq!(
+            ls.values_of_lossy("SUBSTRING").unwrap(),
+            vec!["proj", "2024"]
         );
+        assert_eq!(ls.value_of("match"), Some("all"));
+        assert_eq!(describe_terms(&["proj", "2024"], true), "`proj` and `2024`");
+        assert_eq!(describe_terms(&["proj", "2024"], false), "`proj` or `2024`");
     }
 
-    for conversation in conversations {
-        let (icon, color) = if conversation.is_deleted {
-            ("🗑", Color::Red)
-        } else if conversation.is_archived {
-            ("🗄", Color::Yellow)
-        } else {
-            ("🗒", Color::White)
+    #[tokio::test]
+    async fn substrings_match_any_or_all() {
+        let mut slack = mock_slack();
+        for (id, name) in &[("C0000000003", "proj-2024"), ("C0000000004", "proj-2019")] {
+            slack.conversations.push(public_channel_json(id, name));
+        }
+        let conversations = normalized(&slack).await;
+        let matching = |terms: &[&str], match_all| {
+            let mut names = conversations
+                .iter()
+                .filter(|convo| convo.matches_terms(terms, match_all, false, false))
+                .map(|convo| convo.display_names())
+                .collect::<Vec<String>>();
+            names.sort_unstable();
+            names
         };
-        println!(
-            "{}",
-            format!(
-                "{} {}: {}{}",
-                icon,
-                conversation.id.bold(),
-                &conversation.type_identifier,
-                conversation
-                    .names
-                    .join(&format!(", {}", &conversation.type_identifier))
-            )
-            .color(color)
+
+        assert_eq!(matching(&["proj", "2024"], true), ["#proj-2024"]);
+        assert_eq!(
+            matching(&["2024", "random"], false),
+            ["#proj-2024", "#random"]
+        );
+        assert!(matching(&["2024", "random"], true).is_empty());
+        assert_eq!(matching(&[], true).len(), conversations.len());
+        assert_eq!(matching(&[], false).len(), conversations.len());
+    }
+
+    #[tokio::test]
+    async fn stats_summarize_conversations() {
+        let mut slack = mock_slack();
+        // Only the oldest and newest conversations are named,
+        // so a DM with a user Slack can't find is still counted
+        slack
+            .conversations
+            .insert(1, im_json("D0000000009", "U0000000009"));
+        let types = TYPES.iter().map(|s| s.to_string()).collect::<Vec<String>>();
+        let conversations = slack
+            .list_conversations(&types, false, None, None)
+            .await
+            .unwrap();
+        let named = oldest_and_newest(&slack, &conversations, &ProgressBar::hidden()).await;
+
+        let mut out = vec![];
+        print_stats(&mut out, &conversations, &named, Locale::en).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "public_channel: 2\n\
+             private_channel: 1\n\
+             mpim: 1\n\
+             im: 3\n\
+             archived: 0\n\
+             public_channel members: 6\n\
+             oldest: @bob (D0000000002), created 2019-11-29\n\
+             newest: #general (C0000000001), created 2019-11-29\n"
         );
     }
 
-    #[derive(Debug)]
-    struct NormalizedConversation {
-        id: String,
-        type_identifier: String,
-        names: Vec<String>,
-        is_archived: bool,
-        is_deleted: bool,
+    #[tokio::test]
+    async fn shows_channel_creators() {
+        let slack = mock_slack();
+        let mut conversations = normalized(&slack).await;
+        for conversation in &mut conversations {
+            resolve_creator(
+                &slack,
+                conversation,
+                ProfileField::Name,
+                &ProgressBar::hidden(),
+            )
+            .await;
+        }
+        let annotations = |id| {
+            conversations
+                .iter()
+                .find(|convo| convo.id == id)
+                .unwrap()
+                .annotations()
+        };
+
+        assert_eq!(annotations("C0000000001"), " (created by @alice)");
+        assert_eq!(
+            annotations("G0000000002"),
+            " (created by @carol, deactivated)"
+        );
+        assert_eq!(annotations("D0000000001"), "");
     }
 }
@@ -0,0 +1,163 @@
+//! Turns Slack's mrkdwn message text into plain, readable text.
+//! See https://api.slack.com/reference/surfaces/formatting
+
+use std::collections::HashMap;
+
+/// IDs of users mentioned in `text`, e.g. `U0123456789` from `<@U0123456789>`.
+pub fn mentioned_users(text: &str) -> Vec<String> {
+    let mut users = vec![];
+    for token in tokens(text) {
+        if let Some(user) = token.strip_prefix('@') {
+            let user = user.split('|').next().unwrap_or(user);
+            if !users.iter().any(|seen| seen == user) {
+                users.push(user.to_string());
+            }
+        }
+    }
+    users
+}
+
+/// Replaces mention and link tokens in `text`, e.g. `<@U0123456789>` with `@alice`
+/// using `names`, `<#C0123456789|general>` with `#general`,
+/// and `<https://example.com|Example>` with `Example (https://example.com)`.
+/// Users missing from `names` are left as their ID.
+pub fn resolve_mentions(text: &str, names: &HashMap<String, String>) -> String {
+    let mut resolved = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+        resolved.push_str(&unescape(&rest[..start]));
+        resolved.push_str(&resolve_token(&rest[start + 1..end], names));
+        rest = &rest[end + 1..];
+    }
+    resolved.push_str(&unescape(rest));
+    resolved
+}
+
+fn resolve_token(token: &str, names: &HashMap<String, String>) -> String {
+    let (target, label) = match token.find('|') {
+        Some(index) => (&token[..index], Some(unescape(&token[index + 1..]))),
+        None => (token, None),
+    };
+    if let Some(user) = target.strip_prefix('@') {
+        format!("@{}", names.get(user).map_or(user, String::as_str))
+    } else if let Some(channel) = target.strip_prefix('#') {
+        format!("#{}", label.as_deref().unwrap_or(channel))
+    } else if let Some(special) = target.strip_prefix('!') {
+        // `<!here>`, `<!subteam^S0123456789|@team>`, `<!date^1575000000^{date}|Nov 29th>`
+        label.unwrap_or_else(|| format!("@{}", special))
+    } else {
+        let target = unescape(target);
+        match label {
+            Some(label) if label != target => format!("{} ({})", label, target),
+            _ => target,
+        }
+    }
+}
+
+/// Strips `*bold*`, `_italic_`, `~strikethrough~`, and `` `code` `` markers
+/// around words, leaving markers inside words such as `snake_case` alone.
+pub fn strip_formatting(text: &str) -> String {
+    let text = text.replace("```", "");
+    let chars = text.chars().collect::<Vec<char>>();
+    let is_boundary = |index: Option<usize>| {
+        index
+            .and_then(|index| chars.get(index))
+            .is_none_or(|c| c.is_whitespace() || c.is_ascii_punctuation())
+    };
+
+    let mut stripped = String::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        if "*_~`".contains(c) && is_boundary(index.checked_sub(1)) {
+            let closing = chars[index + 1..]
+                .iter()
+                .position(|&other| other == c)
+                .map(|offset| index + 1 + offset);
+            if let Some(closing) = closing {
+                let inner = &chars[index + 1..closing];
+                let padded = inner.first().is_none_or(|c| c.is_whitespace())
+                    || inner.last().is_some_and(|c| c.is_whitespace());
+                if !padded && is_boundary(Some(closing + 1)) {
+                    stripped.extend(inner);
+                    index = closing + 1;
+                    continue;
+                }
+            }
+        }
+        stripped.push(c);
+        index += 1;
+    }
+    stripped
+}
+
+/// Contents of each `<...>` token in `text`.
+fn tokens(text: &str) -> Vec<&str> {
+    let mut tokens = vec![];
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        match rest[start..].find('>') {
+            Some(end) => {
+                tokens.push(&rest[start + 1..start + end]);
+                rest = &rest[start + end + 1..];
+            }
+            None => break,
+        }
+    }
+    tokens
+}
+
+/// Reverses the only escaping Slack does in message text.
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_each_mentioned_user_once() {
+        assert_eq!(
+            mentioned_users(
+                "<@U0000000001> and <@U0000000002|bob>, not <#C0000000001>. <@U0000000001>!"
+            ),
+            vec!["U0000000001", "U0000000002"]
+        );
+    }
+
+    #[test]
+    fn resolves_mentions_and_links() {
+        let mut names = HashMap::new();
+        names.insert("U0000000001".to_string(), "alice".to_string());
+
+        assert_eq!(
+            resolve_mentions(
+                "<!here> <@U0000000001> asked <@U0000000009> to read <#C0000000001|general> &amp; <https://example.com|the docs> &lt;3",
+                &names
+            ),
+            "@here @alice asked @U0000000009 to read #general & the docs (https://example.com) <3"
+        );
+        assert_eq!(
+            resolve_mentions("<https://example.com|https://example.com>", &names),
+            "https://example.com"
+        );
+        assert_eq!(resolve_mentions("a < b", &names), "a < b");
+    }
+
+    #[test]
+    fn strips_formatting_around_words_only() {
+        assert_eq!(
+            strip_formatting("*Heads up:* _please_ ~don't~ touch `snake_case` or 2*3*4"),
+            "Heads up: please don't touch snake_case or 2*3*4"
+        );
+        assert_eq!(strip_formatting("```let x = 1;```"), "let x = 1;");
+        assert_eq!(strip_formatting("* not a list *"), "* not a list *");
+    }
+}
@@ -0,0 +1,89 @@
+//! Spaces out requests to stay under Slack's rate limits.
+//! See https://api.slack.com/docs/rate-limits
+
+use log::debug;
+use std::time::{Duration, Instant};
+use tokio::time;
+
+/// Token bucket allowing bursts of up to `capacity` requests,
+/// then one request every `interval` as tokens refill.
+pub struct Pacer {
+    interval: Duration,
+    capacity: f64,
+    // Negative while requests are waiting on tokens that haven't refilled yet
+    tokens: f64,
+    refilled: Instant,
+}
+impl Pacer {
+    /// Pacer for `per_minute` requests a minute, starting with a full bucket of `capacity`.
+    pub fn new(per_minute: u32, capacity: u32) -> Pacer {
+        Pacer {
+            interval: Duration::from_secs(60) / per_minute,
+            capacity: f64::from(capacity),
+            tokens: f64::from(capacity),
+            refilled: Instant::now(),
+        }
+    }
+
+    /// Waits until the next request can be sent.
+    pub async fn acquire(&mut self) {
+        let delay = self.reserve(Instant::now());
+        if delay > Duration::from_secs(0) {
+            time::sleep(delay).await;
+        }
+    }
+
+    /// Takes a token as of `now`, returning how long to wait before it's available.
+    fn reserve(&mut self, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.refilled);
+        self.tokens =
+            (self.tokens + elapsed.as_secs_f64() / self.interval.as_secs_f64()).min(self.capacity);
+        self.refilled = now;
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            self.interval.mul_f64(-self.tokens)
+        }
+    }
+
+    /// Spaces requests a quarter further apart and gives up any saved up burst,
+    /// for when Slack rate limits requests despite the pacing.
+    pub fn slow_down(&mut self) {
+        self.interval = self.interval.mul_f64(1.25);
+        self.tokens = self.tokens.min(0.0);
+        debug!(
+            "Slowed down to one request every {}ms",
+            self.interval.as_millis()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bursts_then_spaces_requests() {
+        let mut pacer = Pacer::new(60, 2);
+        let start = pacer.refilled;
+
+        assert_eq!(pacer.reserve(start), Duration::from_secs(0));
+        assert_eq!(pacer.reserve(start), Duration::from_secs(0));
+        assert_eq!(pacer.reserve(start), Duration::from_secs(1));
+        assert_eq!(pacer.reserve(start), Duration::from_secs(2));
+
+        // Waiting out the delays pays back what was borrowed, without saving up a burst
+        let later = start + Duration::from_secs(2);
+        assert_eq!(pacer.reserve(later), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn slows_down_when_rate_limited() {
+        let mut pacer = Pacer::new(60, 5);
+        let start = pacer.refilled;
+
+        pacer.slow_down();
+        assert_eq!(pacer.reserve(start), Duration::from_millis(1250));
+    }
+}
@@ -0,0 +1,53 @@
+use log::debug;
+use std::env;
+use std::io::{self, Write};
+use std::process::{Child, Command, Stdio};
+
+/// Output piped through `$PAGER`.
+/// Waits for the pager to exit when dropped.
+pub struct Pager(Child);
+impl Pager {
+    /// Starts `$PAGER`, defaulting to `less -FR`,
+    /// which keeps colors and exits straight away when output fits on one screen.
+    pub fn spawn() -> Option<Pager> {
+        let command = env::var("PAGER").unwrap_or_else(|_| "less -FR".to_string());
+        let mut args = command.split_whitespace();
+        let program = args.next()?;
+
+        match Command::new(program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .spawn()
+        {
+            Ok(child) => Some(Pager(child)),
+            Err(error) => {
+                debug!("Couldn't start pager `{}`: {}", command, error);
+                None
+            }
+        }
+    }
+}
+// Quitting the pager early closes its stdin,
+// which shouldn't be treated as a failure to print
+impl Write for Pager {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.0.stdin.as_mut().unwrap().write(buf) {
+            Err(ref error) if error.kind() == io::ErrorKind::BrokenPipe => Ok(buf.len()),
+            result => result,
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.0.stdin.as_mut().unwrap().flush() {
+            Err(ref error) if error.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            result => result,
+        }
+    }
+}
+impl Drop for Pager {
+    fn drop(&mut self) {
+        // Closing stdin lets the pager know there's nothing else to show
+        drop(self.0.stdin.take());
+        let _ = self.0.wait();
+    }
+}
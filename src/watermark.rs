@@ -0,0 +1,93 @@
+//! The newest message exported from each conversation, so later exports can pick up after it.
+
+use crate::slack::workspaces_dir;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Directory holding a file per exported conversation, `<config dir>/tidy_slack/exported`.
+pub fn dir() -> Option<PathBuf> {
+    workspaces_dir().map(|dir| dir.join("exported"))
+}
+
+/// Timestamp of the newest message exported from `conversation`, if it was exported before.
+pub fn read(dir: &Path, conversation: &str) -> io::Result<Option<String>> {
+    match fs::read_to_string(dir.join(conversation)) {
+        Ok(ts) => Ok(Some(ts.trim().to_string())),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Records `ts` as the newest message exported from `conversation`,
+/// unless a newer one was already recorded.
+pub fn record(dir: &Path, conversation: &str, ts: &str) -> io::Result<()> {
+    if let Some(recorded) = read(dir, conversation)? {
+        if !is_newer(ts, &recorded) {
+            return Ok(());
+        }
+    }
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join(conversation), ts)
+}
+
+/// Whether message timestamp `ts`, e.g. `1575000000.000100`, is after `than`.
+pub fn is_newer(ts: &str, than: &str) -> bool {
+    parse(ts) > parse(than)
+}
+
+/// The newest of message timestamps `timestamps`.
+pub fn newest<'a>(timestamps: impl IntoIterator<Item = &'a str>) -> Option<&'a str> {
+    timestamps.into_iter().max_by_key(|ts| parse(ts))
+}
+
+/// Splits a timestamp into seconds and the sequence Slack appends to keep them unique.
+fn parse(ts: &str) -> (u64, u64) {
+    let mut parts = ts.splitn(2, '.');
+    let mut next = || parts.next().and_then(|part| part.parse().ok()).unwrap_or(0);
+    (next(), next())
+}
+
+/// Whole seconds of timestamp `ts`.
+pub fn seconds(ts: &str) -> u64 {
+    parse(ts).0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn compares_timestamps_numerically() {
+        assert!(is_newer("1575000000.000100", "1575000000.000099"));
+        assert!(is_newer("1575000001.000000", "1575000000.999999"));
+        assert!(!is_newer("1575000000.000100", "1575000000.000100"));
+        assert!(is_newer("10000000000.000000", "9999999999.000000"));
+        assert_eq!(seconds("1575000000.000100"), 1_575_000_000);
+        assert_eq!(
+            newest(vec![
+                "1575000000.000100",
+                "1575000000.000200",
+                "1574000000.000300"
+            ]),
+            Some("1575000000.000200")
+        );
+    }
+
+    #[test]
+    fn only_records_newer_timestamps() {
+        let dir = env::temp_dir().join(format!("tidy_slack-exported-{}", std::process::id()));
+        assert_eq!(read(&dir, "C0000000001").unwrap(), None);
+
+        record(&dir, "C0000000001", "1575000000.000100").unwrap();
+        record(&dir, "C0000000001", "1574000000.000100").unwrap();
+        let recorded = read(&dir, "C0000000001").unwrap();
+        record(&dir, "C0000000001", "1576000000.000100").unwrap();
+        let updated = read(&dir, "C0000000001").unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(recorded.as_deref(), Some("1575000000.000100"));
+        assert_eq!(updated.as_deref(), Some("1576000000.000100"));
+    }
+}